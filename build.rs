@@ -0,0 +1,4 @@
+fn main() {
+    // migrations are embedded into the binary, rebuild when they change.
+    println!("cargo:rerun-if-changed=migrations");
+}
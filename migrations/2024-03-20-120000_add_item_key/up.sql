@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE auctions ADD COLUMN item_key text;
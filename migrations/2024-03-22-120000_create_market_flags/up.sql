@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE market_flags (
+  id serial primary key,
+  kind text not null,
+  auction_uuid text not null,
+  related_auction_uuid text,
+  item_key text,
+  auctioneer text not null,
+  profile_id text not null,
+  price bigint not null,
+  reference_price bigint,
+  detected_at timestamp not null default now(),
+  reviewed boolean not null default false,
+  unique (kind, auction_uuid)
+)
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+-- Every auction an individual item (by item_uuid) has been listed in, in order. A BIN counts as
+-- sold once it was claimed or the item shows up again under a different seller.
+CREATE VIEW item_lineage AS
+SELECT
+  uuid AS auction_uuid,
+  item_uuid,
+  item_key,
+  item_name,
+  auctioneer,
+  profile_id,
+  price,
+  bin,
+  start_time,
+  end_time,
+  CASE WHEN bin AND (claimed OR lead(auctioneer) OVER w <> auctioneer) THEN price END AS sold_price,
+  lead(auctioneer) OVER w AS next_auctioneer,
+  row_number() OVER w AS listing_number
+FROM auctions
+WHERE item_uuid IS NOT NULL
+WINDOW w AS (PARTITION BY item_uuid ORDER BY start_time)
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE sellers (
+  auctioneer text not null,
+  profile_id text not null,
+  coop text[],
+  active_listings bigint not null,
+  total_listed_value bigint not null,
+  sold_count bigint not null,
+  median_markup double precision,
+  categories text[] not null,
+  refreshed_at timestamp not null,
+  primary key (auctioneer, profile_id)
+)
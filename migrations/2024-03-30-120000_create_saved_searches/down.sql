@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop table saved_search_hits;
+drop table saved_searches
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE saved_searches (
+  id serial primary key,
+  name text not null,
+  webhook_url text not null,
+  item_id text,
+  item_key text,
+  name_contains text,
+  tier text,
+  min_stars integer,
+  min_price bigint,
+  max_price bigint,
+  bin_only boolean not null default true,
+  enabled boolean not null default true,
+  created_at timestamp not null default now()
+);
+
+-- Auctions already sent for a search, so a listing is only ever posted once per search.
+CREATE TABLE saved_search_hits (
+  search_id integer not null references saved_searches (id) on delete cascade,
+  auction_uuid text not null,
+  sent_at timestamp not null default now(),
+  primary key (search_id, auction_uuid)
+)
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- How hits are rendered before posting: 'json' for the raw hit, 'discord' for a webhook embed.
+ALTER TABLE saved_searches ADD COLUMN payload_format text not null default 'json';
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Every auction is either BIN or not, the scraper always writes it.
+UPDATE auctions SET bin = true WHERE bin IS NULL;
+ALTER TABLE auctions ALTER COLUMN bin SET NOT NULL;
@@ -0,0 +1,34 @@
+-- This file should undo anything in `up.sql`
+-- item_lineage reads auctions, it comes back unchanged below.
+DROP VIEW item_lineage;
+ALTER TABLE auctions RENAME TO auctions_partitioned;
+ALTER TABLE auctions_partitioned DROP CONSTRAINT auctions_pkey;
+
+CREATE TABLE auctions (
+  LIKE auctions_partitioned INCLUDING DEFAULTS,
+  PRIMARY KEY (uuid)
+);
+
+-- detached partitions are left alone, only what's still attached comes back.
+INSERT INTO auctions
+SELECT DISTINCT ON (uuid) * FROM auctions_partitioned ORDER BY uuid, last_updated DESC;
+DROP TABLE auctions_partitioned;
+
+CREATE VIEW item_lineage AS
+SELECT
+  uuid AS auction_uuid,
+  item_uuid,
+  item_key,
+  item_name,
+  auctioneer,
+  profile_id,
+  price,
+  bin,
+  start_time,
+  end_time,
+  CASE WHEN bin AND (claimed OR lead(auctioneer) OVER w <> auctioneer) THEN price END AS sold_price,
+  lead(auctioneer) OVER w AS next_auctioneer,
+  row_number() OVER w AS listing_number
+FROM auctions
+WHERE item_uuid IS NOT NULL
+WINDOW w AS (PARTITION BY item_uuid ORDER BY start_time);
@@ -0,0 +1,41 @@
+-- Your SQL goes here
+-- Auctions are partitioned by the month they were listed in rather than the month they ended in.
+-- The partition key has to be part of the primary key, and end_time moves when an auction sells
+-- early, so keyed on it an upsert would stop conflicting with the row it wrote before. start_time
+-- never changes, so the key stays (uuid, start_time) for an auction's whole life. This month's auctions, and past months that still have live ones, stay in
+-- auctions_hot; the partition maintenance job moves every fully ended month out of it into its own
+-- partition and drops or detaches months past the retention.
+
+-- item_lineage reads auctions, it comes back unchanged below.
+DROP VIEW item_lineage;
+ALTER TABLE auctions RENAME TO auctions_unpartitioned;
+ALTER TABLE auctions_unpartitioned DROP CONSTRAINT auctions_pkey;
+
+CREATE TABLE auctions (
+  LIKE auctions_unpartitioned INCLUDING DEFAULTS,
+  PRIMARY KEY (uuid, start_time)
+) PARTITION BY RANGE (start_time);
+
+CREATE TABLE auctions_hot PARTITION OF auctions DEFAULT;
+
+INSERT INTO auctions SELECT * FROM auctions_unpartitioned;
+DROP TABLE auctions_unpartitioned;
+
+CREATE VIEW item_lineage AS
+SELECT
+  uuid AS auction_uuid,
+  item_uuid,
+  item_key,
+  item_name,
+  auctioneer,
+  profile_id,
+  price,
+  bin,
+  start_time,
+  end_time,
+  CASE WHEN bin AND (claimed OR lead(auctioneer) OVER w <> auctioneer) THEN price END AS sold_price,
+  lead(auctioneer) OVER w AS next_auctioneer,
+  row_number() OVER w AS listing_number
+FROM auctions
+WHERE item_uuid IS NOT NULL
+WINDOW w AS (PARTITION BY item_uuid ORDER BY start_time);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX auctions_bin_item_key_price;
+DROP INDEX auctions_sold_item_key_price;
+DROP INDEX auctions_bin_item_key_start_time;
+DROP INDEX auctions_item_id_price;
+DROP INDEX auctions_auctioneer;
+DROP INDEX auctions_item_uuid_start_time;
+DROP INDEX auctions_end_time;
+DROP INDEX auctions_start_time;
+DROP INDEX auctions_enchantments;
+DROP INDEX auctions_runes;
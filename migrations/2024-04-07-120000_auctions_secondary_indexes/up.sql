@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Indexes on auctions are created on every partition. Partitions go by start month, so lookups
+-- of open auctions by end_time probe each partition's index, past months only hold ended ones.
+
+-- Open BIN floors by item key, cheapest first: lowest BIN, flips, outliers and seller markups.
+CREATE INDEX auctions_bin_item_key_price ON auctions (item_key, price) INCLUDE (end_time)
+    WHERE bin;
+-- Sold BINs by item key for the median sold price.
+CREATE INDEX auctions_sold_item_key_price ON auctions (item_key, price) WHERE bin AND claimed;
+-- BIN listings of an item key over time for price history.
+CREATE INDEX auctions_bin_item_key_start_time ON auctions (item_key, start_time) WHERE bin;
+CREATE INDEX auctions_item_id_price ON auctions (item_id, price);
+-- A seller's listings, and their latest coop for seller stats.
+CREATE INDEX auctions_auctioneer ON auctions (auctioneer, profile_id, last_updated);
+-- Every listing of an individual item for item_lineage and relist detection.
+CREATE INDEX auctions_item_uuid_start_time ON auctions (item_uuid, start_time)
+    WHERE item_uuid IS NOT NULL;
+-- The primary key leads with uuid, so ranges on end_time or start_time need their own.
+CREATE INDEX auctions_end_time ON auctions (end_time);
+CREATE INDEX auctions_start_time ON auctions (start_time);
+-- Containment and overlap (@>, &&) on "name level" entries.
+CREATE INDEX auctions_enchantments ON auctions USING gin (enchantments);
+CREATE INDEX auctions_runes ON auctions USING gin (runes);
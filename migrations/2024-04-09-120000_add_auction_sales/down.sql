@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+CREATE OR REPLACE VIEW item_lineage AS
+SELECT
+  uuid AS auction_uuid,
+  item_uuid,
+  item_key,
+  item_name,
+  auctioneer,
+  profile_id,
+  price,
+  bin,
+  start_time,
+  end_time,
+  CASE WHEN bin AND (claimed OR lead(auctioneer) OVER w <> auctioneer) THEN price END AS sold_price,
+  lead(auctioneer) OVER w AS next_auctioneer,
+  row_number() OVER w AS listing_number
+FROM auctions
+WHERE item_uuid IS NOT NULL
+WINDOW w AS (PARTITION BY item_uuid ORDER BY start_time);
+
+ALTER TABLE auctions DROP COLUMN sold_price;
+ALTER TABLE auctions DROP COLUMN buyer;
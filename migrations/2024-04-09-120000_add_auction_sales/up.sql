@@ -0,0 +1,28 @@
+-- Your SQL goes here
+-- Sales from the ended auctions endpoint, on the auction that sold.
+ALTER TABLE auctions ADD COLUMN buyer text;
+ALTER TABLE auctions ADD COLUMN sold_price bigint;
+
+-- A recorded sale is the real price, including auctions that ended on a bid. Without one a BIN
+-- still counts as sold when it was claimed or the item turned up with someone else.
+CREATE OR REPLACE VIEW item_lineage AS
+SELECT
+  uuid AS auction_uuid,
+  item_uuid,
+  item_key,
+  item_name,
+  auctioneer,
+  profile_id,
+  price,
+  bin,
+  start_time,
+  end_time,
+  coalesce(
+    sold_price,
+    CASE WHEN bin AND (claimed OR lead(auctioneer) OVER w <> auctioneer) THEN price END
+  ) AS sold_price,
+  lead(auctioneer) OVER w AS next_auctioneer,
+  row_number() OVER w AS listing_number
+FROM auctions
+WHERE item_uuid IS NOT NULL
+WINDOW w AS (PARTITION BY item_uuid ORDER BY start_time);
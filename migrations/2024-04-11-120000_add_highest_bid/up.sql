@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- price is the starting bid and never changes, this follows the bidding. Unknown for auctions
+-- stored before it.
+ALTER TABLE auctions ADD COLUMN highest_bid bigint;
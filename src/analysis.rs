@@ -0,0 +1,125 @@
+use crate::models::MarketFlag;
+use crate::schema::market_flags;
+use crate::AHScraperError;
+use diesel::sql_types::{BigInt, Double};
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use std::time::Duration;
+
+/// Tuning for the manipulation checks.
+#[derive(Debug, Clone)]
+pub struct AnalysisConfig {
+    /// How many interquartile ranges outside of Q1/Q3 a price has to be to get flagged.
+    pub iqr_multiplier: f64,
+    /// Open BIN listings an item key needs before we trust its distribution.
+    pub min_samples: i64,
+    /// Minimum markup (0.2 = 20%) on a relisted item for it to count as a buy and relist.
+    pub relist_markup: f64,
+    /// How soon after the original listing the relist has to appear.
+    pub relist_window: Duration,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            iqr_multiplier: 3.0,
+            min_samples: 8,
+            relist_markup: 0.2,
+            relist_window: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+}
+
+/// Flags open BIN listings priced far outside the rest of their item key, returning how many new
+/// flags were stored.
+pub async fn detect_outliers(
+    conn: &mut AsyncPgConnection,
+    config: &AnalysisConfig,
+) -> Result<usize, AHScraperError> {
+    Ok(diesel::sql_query(
+        "WITH stats AS ( \
+            SELECT item_key, \
+                   percentile_cont(0.25) WITHIN GROUP (ORDER BY price) AS q1, \
+                   percentile_cont(0.5) WITHIN GROUP (ORDER BY price) AS median, \
+                   percentile_cont(0.75) WITHIN GROUP (ORDER BY price) AS q3, \
+                   count(*) AS samples \
+            FROM auctions \
+            WHERE bin AND item_key IS NOT NULL AND end_time > (now() AT TIME ZONE 'UTC') \
+            GROUP BY item_key \
+         ) \
+         INSERT INTO market_flags \
+            (kind, auction_uuid, item_key, auctioneer, profile_id, price, reference_price) \
+         SELECT CASE WHEN a.price > s.q3 THEN 'overpriced' ELSE 'underpriced' END, \
+                a.uuid, a.item_key, a.auctioneer, a.profile_id, a.price, s.median::bigint \
+         FROM auctions a JOIN stats s ON a.item_key = s.item_key \
+         WHERE a.bin AND a.end_time > (now() AT TIME ZONE 'UTC') AND s.samples >= $1 \
+           AND (a.price > s.q3 + $2 * (s.q3 - s.q1) OR a.price < s.q1 - $2 * (s.q3 - s.q1)) \
+         ON CONFLICT (kind, auction_uuid) DO NOTHING",
+    )
+    .bind::<BigInt, _>(config.min_samples)
+    .bind::<Double, _>(config.iqr_multiplier)
+    .execute(conn)
+    .await?)
+}
+
+/// Flags items bought from a BIN listing and relisted by the buyer at a markup shortly after,
+/// which is what buying out the floor and relisting it looks like from the outside.
+pub async fn detect_relists(
+    conn: &mut AsyncPgConnection,
+    config: &AnalysisConfig,
+) -> Result<usize, AHScraperError> {
+    Ok(diesel::sql_query(
+        "INSERT INTO market_flags \
+            (kind, auction_uuid, related_auction_uuid, item_key, auctioneer, profile_id, price, \
+             reference_price) \
+         SELECT DISTINCT ON (b.uuid) 'relist', b.uuid, a.uuid, b.item_key, b.auctioneer, \
+                b.profile_id, b.price, a.sold_price \
+         FROM auctions a \
+         JOIN auctions b ON b.item_uuid = a.item_uuid AND b.uuid <> a.uuid \
+         WHERE a.item_uuid IS NOT NULL AND a.bin \
+           AND a.claimed AND a.sold_price IS NOT NULL AND a.buyer = b.auctioneer \
+           AND b.start_time > a.start_time \
+           AND b.start_time < a.start_time + make_interval(secs => $1) \
+           AND b.price >= a.sold_price * (1 + $2) \
+         ORDER BY b.uuid, a.start_time DESC \
+         ON CONFLICT (kind, auction_uuid) DO NOTHING",
+    )
+    .bind::<Double, _>(config.relist_window.as_secs_f64())
+    .bind::<Double, _>(config.relist_markup)
+    .execute(conn)
+    .await?)
+}
+
+/// Runs every check once, returning the total number of new flags.
+pub async fn run_analysis(
+    db: Pool<AsyncPgConnection>,
+    config: &AnalysisConfig,
+) -> Result<usize, AHScraperError> {
+    let mut conn = db.get().await?;
+    let outliers = detect_outliers(&mut conn, config).await?;
+    let relists = detect_relists(&mut conn, config).await?;
+    Ok(outliers + relists)
+}
+
+/// Flags nobody has looked at yet, oldest first.
+pub async fn unreviewed_flags(
+    conn: &mut AsyncPgConnection,
+    limit: i64,
+) -> Result<Vec<MarketFlag>, AHScraperError> {
+    Ok(market_flags::table
+        .filter(market_flags::reviewed.eq(false))
+        .order(market_flags::detected_at.asc())
+        .limit(limit)
+        .select(MarketFlag::as_select())
+        .load(conn)
+        .await?)
+}
+
+pub async fn mark_reviewed(conn: &mut AsyncPgConnection, id: i32) -> Result<(), AHScraperError> {
+    diesel::update(market_flags::table.find(id))
+        .set(market_flags::reviewed.eq(true))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use crate::book::{BookQuery, SharedBook};
+use crate::events::{EventFilter, EventSender};
+use crate::models::AuctionSummary;
+use crate::schema::auctions;
+use crate::shutdown::Shutdown;
+use crate::supervisor::{JobStatus, Supervisor};
+use crate::AHScraperError;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use diesel::sql_types::{BigInt, Text, Timestamp};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, QueryableByName, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, TimestampMilliSeconds};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::error::RecvError;
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+/// Longer than SkyBlock has been around.
+const MAX_HISTORY_DAYS: u64 = 10 * 365;
+
+#[derive(Clone)]
+struct AppState {
+    db: Pool<AsyncPgConnection>,
+    book: SharedBook,
+    events: EventSender,
+    supervisor: Supervisor,
+    shutdown: Shutdown,
+}
+
+pub fn router(
+    db: Pool<AsyncPgConnection>,
+    book: SharedBook,
+    events: EventSender,
+    supervisor: Supervisor,
+    shutdown: Shutdown,
+) -> Router {
+    Router::new()
+        .route("/auctions", get(list_auctions))
+        .route("/auctions/:uuid", get(get_auction))
+        .route("/live_auctions", get(live_auctions))
+        .route("/lowest_bin/:item_key", get(lowest_bin))
+        .route("/price_history/:item_key", get(price_history))
+        .route("/events", get(event_stream))
+        .route("/jobs", get(jobs))
+        .with_state(AppState {
+            db,
+            book,
+            events,
+            supervisor,
+            shutdown,
+        })
+}
+
+/// Serves the read only API on `addr` until the process exits.
+pub async fn serve(
+    db: Pool<AsyncPgConnection>,
+    book: SharedBook,
+    events: EventSender,
+    supervisor: Supervisor,
+    shutdown: Shutdown,
+    addr: &str,
+) -> Result<(), std::io::Error> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("Serving query API on {}", addr);
+    let app = router(db, book, events, supervisor, shutdown.clone());
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await
+}
+
+impl IntoResponse for AHScraperError {
+    fn into_response(self) -> Response {
+        if let AHScraperError::InvalidArgument(message) = self {
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+        // the details stay in the log, they can carry database and driver internals.
+        println!("API request failed: {}", self);
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+impl Pagination {
+    fn limit(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn offset(&self) -> i64 {
+        self.page.unwrap_or(0).max(0) * self.limit()
+    }
+}
+
+#[derive(Serialize)]
+struct Paged<T> {
+    page: i64,
+    per_page: i64,
+    items: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct AuctionFilter {
+    item_id: Option<String>,
+    item_key: Option<String>,
+    bin: Option<bool>,
+    min_price: Option<i64>,
+    max_price: Option<i64>,
+    /// Only auctions that haven't ended yet, defaults to true.
+    active: Option<bool>,
+}
+
+async fn list_auctions(
+    State(state): State<AppState>,
+    Query(filter): Query<AuctionFilter>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Paged<AuctionSummary>>, AHScraperError> {
+    let mut conn = state.db.get().await?;
+    let mut query = auctions::table.into_boxed();
+    if let Some(item_id) = filter.item_id {
+        query = query.filter(auctions::item_id.eq(item_id));
+    }
+    if let Some(item_key) = filter.item_key {
+        query = query.filter(auctions::item_key.eq(item_key));
+    }
+    if let Some(bin) = filter.bin {
+        query = query.filter(auctions::bin.eq(bin));
+    }
+    if let Some(min_price) = filter.min_price {
+        query = query.filter(auctions::price.ge(min_price));
+    }
+    if let Some(max_price) = filter.max_price {
+        query = query.filter(auctions::price.le(max_price));
+    }
+    if filter.active.unwrap_or(true) {
+        query = query.filter(auctions::end_time.gt(SystemTime::now()));
+    }
+    let items = query
+        .order((auctions::price.asc(), auctions::uuid.asc()))
+        .limit(pagination.limit())
+        .offset(pagination.offset())
+        .select(AuctionSummary::as_select())
+        .load(&mut conn)
+        .await?;
+    Ok(Json(Paged {
+        page: pagination.page.unwrap_or(0),
+        per_page: pagination.limit(),
+        items,
+    }))
+}
+
+async fn get_auction(
+    State(state): State<AppState>,
+    Path(uuid): Path<String>,
+) -> Result<Response, AHScraperError> {
+    let mut conn = state.db.get().await?;
+    let auction = auctions::table
+        .filter(auctions::uuid.eq(uuid))
+        .select(AuctionSummary::as_select())
+        .first(&mut conn)
+        .await
+        .optional()?;
+    Ok(match auction {
+        Some(auction) => Json(auction).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
+}
+
+#[derive(Serialize)]
+struct LowestBin {
+    item_key: String,
+    lowest_bin: Option<i64>,
+}
+
+async fn lowest_bin(
+    State(state): State<AppState>,
+    Path(item_key): Path<String>,
+) -> Json<LowestBin> {
+    let lowest_bin = state.book.read().unwrap().lowest_bin(&item_key, None);
+    Json(LowestBin {
+        item_key,
+        lowest_bin,
+    })
+}
+
+/// Live auctions by item, price range and BIN, cheapest first, answered from the auction book.
+async fn live_auctions(
+    State(state): State<AppState>,
+    Query(mut query): Query<BookQuery>,
+) -> Json<Vec<AuctionSummary>> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE as usize);
+    query.limit = Some(limit.clamp(1, MAX_PAGE_SIZE as usize));
+    let book = state.book.read().unwrap();
+    Json(book.query(&query).into_iter().map(Into::into).collect())
+}
+
+#[derive(Deserialize)]
+struct HistoryRange {
+    /// How many days back to go, defaults to 30 and at most [`MAX_HISTORY_DAYS`].
+    days: Option<u64>,
+}
+
+#[serde_as]
+#[derive(QueryableByName, Serialize)]
+struct PricePoint {
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    #[diesel(sql_type = Timestamp)]
+    day: SystemTime,
+    #[diesel(sql_type = BigInt)]
+    lowest: i64,
+    #[diesel(sql_type = BigInt)]
+    median: i64,
+    #[diesel(sql_type = BigInt)]
+    listings: i64,
+}
+
+/// Daily lowest and median BIN price of everything listed under the item key.
+async fn price_history(
+    State(state): State<AppState>,
+    Path(item_key): Path<String>,
+    Query(range): Query<HistoryRange>,
+) -> Result<Json<Vec<PricePoint>>, AHScraperError> {
+    let days = range.days.unwrap_or(30);
+    let since = Some(days)
+        .filter(|days| *days <= MAX_HISTORY_DAYS)
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 60 * 60)))
+        .ok_or_else(|| {
+            AHScraperError::InvalidArgument(format!(
+                "days must be at most {}, got {}",
+                MAX_HISTORY_DAYS, days
+            ))
+        })?;
+    let mut conn = state.db.get().await?;
+    let points = diesel::sql_query(
+        "SELECT date_trunc('day', start_time) AS day, \
+                min(price) AS lowest, \
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY price)::bigint AS median, \
+                count(*) AS listings \
+         FROM auctions \
+         WHERE item_key = $1 AND bin AND start_time >= $2 \
+         GROUP BY day ORDER BY day",
+    )
+    .bind::<Text, _>(item_key)
+    .bind::<Timestamp, _>(since)
+    .load(&mut conn)
+    .await?;
+    Ok(Json(points))
+}
+
+/// Server-Sent Events stream of auction events matching the query's filter, ended on shutdown so
+/// the server can stop.
+async fn event_stream(
+    State(state): State<AppState>,
+    Query(filter): Query<EventFilter>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.events.subscribe();
+    let stream = futures::stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if filter.matches(&event) => {
+                    let sse = Event::default().json_data(&event);
+                    return Some((sse, (receiver, filter)));
+                }
+                // a subscriber that can't keep up just misses events.
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let shutdown = state.shutdown.clone();
+    let stream = stream.take_until(async move { shutdown.cancelled().await });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// How each background job is doing, including its last error.
+async fn jobs(State(state): State<AppState>) -> Json<BTreeMap<&'static str, JobStatus>> {
+    Json(state.supervisor.statuses())
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// zstd level 3 compresses auction pages about 10x while keeping up with the scrape loop.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// How much archived data to keep, every set limit is enforced with the oldest snapshots going
+/// first.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Drop snapshots whose `lastUpdated` is older than this.
+    pub max_age: Option<Duration>,
+    /// Keep at most this many snapshots.
+    pub max_snapshots: Option<usize>,
+    /// Keep the archive under this many bytes on disk.
+    pub max_bytes: Option<u64>,
+}
+
+/// Writes every raw auctions page to `{dir}/{lastUpdated}/page-{page}.json.zst`, so the exact
+/// state of the AH at each API refresh can be rebuilt later.
+#[derive(Debug, Clone)]
+pub struct SnapshotArchiver {
+    dir: PathBuf,
+    retention: RetentionPolicy,
+}
+
+impl SnapshotArchiver {
+    pub fn new(dir: impl Into<PathBuf>, retention: RetentionPolicy) -> Self {
+        SnapshotArchiver {
+            dir: dir.into(),
+            retention,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn page_path(&self, last_updated: u64, page: u32) -> PathBuf {
+        self.dir
+            .join(last_updated.to_string())
+            .join(format!("page-{}.json.zst", page))
+    }
+
+    /// Stores a page unless this refresh of it was already archived. Pages are written to a
+    /// temporary file first so a crash never leaves a truncated page behind.
+    pub fn store(&self, last_updated: u64, page: u32, body: &[u8]) -> io::Result<()> {
+        let path = self.page_path(last_updated, page);
+        if path.exists() {
+            return Ok(());
+        }
+        let snapshot_dir = path.parent().expect("page paths always have a parent");
+        let new_snapshot = !snapshot_dir.exists();
+        fs::create_dir_all(snapshot_dir)?;
+        let tmp = path.with_extension("zst.tmp");
+        fs::write(&tmp, zstd::encode_all(body, COMPRESSION_LEVEL)?)?;
+        fs::rename(&tmp, &path)?;
+        if new_snapshot {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// Every archived snapshot's `lastUpdated`, oldest first.
+    pub fn snapshots(&self) -> io::Result<Vec<u64>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots: Vec<u64> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        snapshots.sort_unstable();
+        Ok(snapshots)
+    }
+
+    fn snapshot_size(&self, last_updated: u64) -> io::Result<u64> {
+        let mut size = 0;
+        for entry in fs::read_dir(self.dir.join(last_updated.to_string()))? {
+            size += entry?.metadata()?.len();
+        }
+        Ok(size)
+    }
+
+    /// Deletes snapshots until the retention policy is met, returning how many were removed.
+    pub fn prune(&self) -> io::Result<usize> {
+        let snapshots = self.snapshots()?;
+        let mut keep_from = 0;
+        if let Some(max_age) = self.retention.max_age {
+            let cutoff = SystemTime::now()
+                .checked_sub(max_age)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis() as u64);
+            keep_from = snapshots.partition_point(|&s| s < cutoff);
+        }
+        if let Some(max_snapshots) = self.retention.max_snapshots {
+            keep_from = keep_from.max(snapshots.len().saturating_sub(max_snapshots));
+        }
+        if let Some(max_bytes) = self.retention.max_bytes {
+            let sizes = snapshots
+                .iter()
+                .map(|&s| self.snapshot_size(s))
+                .collect::<io::Result<Vec<_>>>()?;
+            let mut total: u64 = sizes[keep_from..].iter().sum();
+            while total > max_bytes && keep_from < snapshots.len() {
+                total -= sizes[keep_from];
+                keep_from += 1;
+            }
+        }
+        for snapshot in &snapshots[..keep_from] {
+            fs::remove_dir_all(self.dir.join(snapshot.to_string()))?;
+        }
+        Ok(keep_from)
+    }
+}
+
+/// Reads an archived page back into its original JSON.
+pub fn read_page(path: &Path) -> io::Result<Vec<u8>> {
+    zstd::decode_all(fs::File::open(path)?)
+}
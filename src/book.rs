@@ -0,0 +1,275 @@
+use crate::models::Auction as AuctionModel;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// The book the scrape loop keeps current, shared with alerting, flip finding and the API.
+pub type SharedBook = Arc<RwLock<AuctionBook>>;
+
+/// A listing in a price index, the uuid keeps listings at the same price apart.
+type PriceEntry = (i64, String);
+
+/// Every live auction held in memory, indexed by uuid, item id, item key, price and end time, so
+/// the lookups made for each new listing don't have to go to the database.
+#[derive(Default)]
+pub struct AuctionBook {
+    auctions: HashMap<String, AuctionModel>,
+    by_item_id: HashMap<String, BTreeSet<PriceEntry>>,
+    by_item_key: HashMap<String, BTreeSet<PriceEntry>>,
+    by_price: BTreeSet<PriceEntry>,
+    by_end: BTreeSet<(SystemTime, String)>,
+}
+
+/// Which live auctions to return, everything set has to match.
+#[derive(Debug, Default, Deserialize)]
+pub struct BookQuery {
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
+    #[serde(default)]
+    pub bin_only: bool,
+    pub limit: Option<usize>,
+}
+
+impl AuctionBook {
+    pub fn len(&self) -> usize {
+        self.auctions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.auctions.is_empty()
+    }
+
+    pub fn get(&self, uuid: &str) -> Option<&AuctionModel> {
+        self.auctions.get(uuid)
+    }
+
+    /// Adds an auction or replaces the stored copy, moving it in the indexes if its price or end
+    /// time changed.
+    pub fn insert(&mut self, auction: AuctionModel) {
+        self.remove(&auction.uuid);
+        let entry = (auction.price, auction.uuid.clone());
+        if let Some(item_id) = &auction.item_id {
+            self.by_item_id
+                .entry(item_id.clone())
+                .or_default()
+                .insert(entry.clone());
+        }
+        if let Some(item_key) = &auction.item_key {
+            self.by_item_key
+                .entry(item_key.clone())
+                .or_default()
+                .insert(entry.clone());
+        }
+        self.by_price.insert(entry);
+        self.by_end.insert((auction.end_time, auction.uuid.clone()));
+        self.auctions.insert(auction.uuid.clone(), auction);
+    }
+
+    pub fn extend(&mut self, auctions: impl IntoIterator<Item = AuctionModel>) {
+        for auction in auctions {
+            self.insert(auction);
+        }
+    }
+
+    pub fn remove(&mut self, uuid: &str) -> Option<AuctionModel> {
+        let auction = self.auctions.remove(uuid)?;
+        let entry = (auction.price, auction.uuid.clone());
+        if let Some(item_id) = &auction.item_id {
+            remove_entry(&mut self.by_item_id, item_id, &entry);
+        }
+        if let Some(item_key) = &auction.item_key {
+            remove_entry(&mut self.by_item_key, item_key, &entry);
+        }
+        self.by_price.remove(&entry);
+        self.by_end.remove(&(auction.end_time, entry.1));
+        Some(auction)
+    }
+
+    /// Drops every auction that ended by `now`, returning how many there were.
+    pub fn expire(&mut self, now: SystemTime) -> usize {
+        let ended: Vec<String> = self
+            .by_end
+            .iter()
+            .take_while(|(end, _)| *end <= now)
+            .map(|(_, uuid)| uuid.clone())
+            .collect();
+        for uuid in &ended {
+            self.remove(uuid);
+        }
+        ended.len()
+    }
+
+    /// Cheapest BIN listed under the item key, optionally ignoring the listing being evaluated.
+    pub fn lowest_bin(&self, item_key: &str, exclude_uuid: Option<&str>) -> Option<i64> {
+        self.by_item_key(item_key)
+            .find(|auction| auction.bin && Some(auction.uuid.as_str()) != exclude_uuid)
+            .map(|auction| auction.price)
+    }
+
+    /// Live auctions of an item key, cheapest first.
+    pub fn by_item_key(&self, item_key: &str) -> impl Iterator<Item = &AuctionModel> {
+        self.resolve(self.by_item_key.get(item_key).into_iter().flatten())
+    }
+
+    /// Live auctions of an item id, whatever their upgrades, cheapest first.
+    pub fn by_item_id(&self, item_id: &str) -> impl Iterator<Item = &AuctionModel> {
+        self.resolve(self.by_item_id.get(item_id).into_iter().flatten())
+    }
+
+    /// Live auctions priced from `min` to `max` inclusive, cheapest first.
+    pub fn price_range(&self, min: i64, max: i64) -> impl Iterator<Item = &AuctionModel> {
+        self.resolve(price_range(&self.by_price, min, max))
+    }
+
+    /// Live auctions matching `query`, cheapest first. Only the listings under the query's item
+    /// key or item id, if it has one, are looked at.
+    pub fn query(&self, query: &BookQuery) -> Vec<&AuctionModel> {
+        let entries = match (&query.item_key, &query.item_id) {
+            (Some(item_key), _) => self.by_item_key.get(item_key),
+            (None, Some(item_id)) => self.by_item_id.get(item_id),
+            (None, None) => Some(&self.by_price),
+        };
+        let Some(entries) = entries else {
+            return Vec::new();
+        };
+        let min = query.min_price.unwrap_or(i64::MIN);
+        let max = query.max_price.unwrap_or(i64::MAX);
+        self.resolve(price_range(entries, min, max))
+            .filter(|auction| query.item_id.is_none() || query.item_id == auction.item_id)
+            .filter(|auction| !query.bin_only || auction.bin)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        entries: impl Iterator<Item = &'a PriceEntry> + 'a,
+    ) -> impl Iterator<Item = &'a AuctionModel> + 'a {
+        entries.filter_map(|(_, uuid)| self.auctions.get(uuid))
+    }
+}
+
+fn price_range(
+    entries: &BTreeSet<PriceEntry>,
+    min: i64,
+    max: i64,
+) -> impl Iterator<Item = &PriceEntry> {
+    entries
+        .range((min, String::new())..)
+        .take_while(move |(price, _)| *price <= max)
+}
+
+fn remove_entry(index: &mut HashMap<String, BTreeSet<PriceEntry>>, key: &str, entry: &PriceEntry) {
+    if let Some(entries) = index.get_mut(key) {
+        entries.remove(entry);
+        if entries.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_auction;
+    use std::time::Duration;
+
+    fn uuids<'a>(auctions: impl Iterator<Item = &'a AuctionModel>) -> Vec<&'a str> {
+        auctions.map(|auction| auction.uuid.as_str()).collect()
+    }
+
+    #[test]
+    fn insert_indexes_by_key_id_and_price() {
+        let mut book = AuctionBook::default();
+        book.extend([
+            test_auction("a", "HYPERION", 300, true),
+            test_auction("b", "HYPERION", 100, true),
+            test_auction("c", "TERMINATOR", 200, true),
+        ]);
+        assert_eq!(book.len(), 3);
+        assert_eq!(uuids(book.by_item_key("HYPERION")), ["b", "a"]);
+        assert_eq!(uuids(book.by_item_id("TERMINATOR")), ["c"]);
+        assert_eq!(uuids(book.price_range(150, 300)), ["c", "a"]);
+    }
+
+    #[test]
+    fn reinserting_moves_a_repriced_auction() {
+        let mut book = AuctionBook::default();
+        book.extend([
+            test_auction("a", "HYPERION", 100, true),
+            test_auction("b", "HYPERION", 200, true),
+        ]);
+        book.insert(test_auction("a", "HYPERION", 300, true));
+        assert_eq!(book.len(), 2);
+        assert_eq!(uuids(book.by_item_key("HYPERION")), ["b", "a"]);
+        assert_eq!(uuids(book.price_range(0, 150)), Vec::<&str>::new());
+        assert_eq!(book.get("a").map(|auction| auction.price), Some(300));
+    }
+
+    #[test]
+    fn remove_clears_every_index() {
+        let mut book = AuctionBook::default();
+        book.insert(test_auction("a", "HYPERION", 100, true));
+        assert_eq!(book.remove("a").map(|auction| auction.price), Some(100));
+        assert!(book.remove("a").is_none());
+        assert!(book.is_empty());
+        assert_eq!(book.by_item_key("HYPERION").count(), 0);
+        assert_eq!(book.by_item_id("HYPERION").count(), 0);
+        assert_eq!(book.price_range(i64::MIN, i64::MAX).count(), 0);
+        assert_eq!(book.expire(SystemTime::now()), 0);
+    }
+
+    #[test]
+    fn expire_drops_what_ended() {
+        let mut book = AuctionBook::default();
+        let ending = test_auction("a", "HYPERION", 100, true);
+        let mut later = test_auction("b", "HYPERION", 200, true);
+        later.end_time += Duration::from_secs(60 * 60);
+        let ended_at = ending.end_time;
+        book.extend([ending, later]);
+        assert_eq!(book.expire(ended_at - Duration::from_secs(1)), 0);
+        assert_eq!(book.expire(ended_at), 1);
+        assert_eq!(uuids(book.by_item_key("HYPERION")), ["b"]);
+    }
+
+    #[test]
+    fn lowest_bin_skips_auctions_and_the_excluded_listing() {
+        let mut book = AuctionBook::default();
+        book.extend([
+            test_auction("bid", "HYPERION", 50, false),
+            test_auction("a", "HYPERION", 100, true),
+            test_auction("b", "HYPERION", 200, true),
+        ]);
+        assert_eq!(book.lowest_bin("HYPERION", None), Some(100));
+        assert_eq!(book.lowest_bin("HYPERION", Some("a")), Some(200));
+        assert_eq!(book.lowest_bin("HYPERION", Some("b")), Some(100));
+        assert_eq!(book.lowest_bin("TERMINATOR", None), None);
+    }
+
+    #[test]
+    fn query_filters_within_the_item_index() {
+        let mut book = AuctionBook::default();
+        book.extend([
+            test_auction("bid", "HYPERION", 50, false),
+            test_auction("a", "HYPERION", 100, true),
+            test_auction("b", "HYPERION", 200, true),
+            test_auction("c", "TERMINATOR", 150, true),
+        ]);
+        let query = BookQuery {
+            item_key: Some("HYPERION".to_string()),
+            max_price: Some(150),
+            bin_only: true,
+            ..BookQuery::default()
+        };
+        assert_eq!(uuids(book.query(&query).into_iter()), ["a"]);
+        let query = BookQuery {
+            min_price: Some(100),
+            limit: Some(2),
+            ..BookQuery::default()
+        };
+        assert_eq!(uuids(book.query(&query).into_iter()), ["a", "c"]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::hypixel_api::auction::Auction;
+use crate::models::Auction as AuctionModel;
+use crate::AHScraperError;
+use std::pin::pin;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::Client;
+
+/// Every column of `auctions`, in the order [`row`] writes them.
+const COLUMNS: [(&str, Type); 45] = [
+    ("uuid", Type::TEXT),
+    ("auctioneer", Type::TEXT),
+    ("profile_id", Type::TEXT),
+    ("coop", Type::TEXT_ARRAY),
+    ("start_time", Type::TIMESTAMP),
+    ("end_time", Type::TIMESTAMP),
+    ("item_name", Type::TEXT),
+    ("item_uuid", Type::TEXT),
+    ("item_lore", Type::TEXT),
+    ("item_id", Type::TEXT),
+    ("item_count", Type::INT4),
+    ("item_damage", Type::INT4),
+    ("enchantments", Type::TEXT_ARRAY),
+    ("unbreakable", Type::BOOL),
+    ("price", Type::INT8),
+    ("claimed", Type::BOOL),
+    ("tier", Type::TEXT),
+    ("category", Type::TEXT),
+    ("last_updated", Type::TIMESTAMP),
+    ("bin", Type::BOOL),
+    ("reforge", Type::TEXT),
+    ("upgrade_level", Type::INT4),
+    ("hot_potato_count", Type::INT4),
+    ("recomb", Type::BOOL),
+    ("unlocked_gem_slots", Type::TEXT_ARRAY),
+    ("slotted_gems", Type::TEXT_ARRAY),
+    ("pet_active", Type::BOOL),
+    ("pet_type", Type::TEXT),
+    ("pet_held_item", Type::TEXT),
+    ("pet_exp", Type::INT4),
+    ("pet_candy_used", Type::INT4),
+    ("dungeon_item_level", Type::INT4),
+    ("red_armor_coloring", Type::INT4),
+    ("green_armor_coloring", Type::INT4),
+    ("blue_armor_coloring", Type::INT4),
+    ("anvil_uses", Type::INT4),
+    ("pelts_earned", Type::INT4),
+    ("champion_combat_xp", Type::INT4),
+    ("farmed_cultivating", Type::INT4),
+    ("compact_blocks", Type::INT4),
+    ("hecatomb_s_runs", Type::INT4),
+    ("expertise_kills", Type::INT4),
+    ("runes", Type::TEXT_ARRAY),
+    ("item_key", Type::TEXT),
+    ("highest_bid", Type::INT8),
+];
+
+fn row(a: &AuctionModel) -> [&(dyn ToSql + Sync); 45] {
+    [
+        &a.uuid,
+        &a.auctioneer,
+        &a.profile_id,
+        &a.coop,
+        &a.start_time,
+        &a.end_time,
+        &a.item_name,
+        &a.item_uuid,
+        &a.item_lore,
+        &a.item_id,
+        &a.item_count,
+        &a.item_damage,
+        &a.enchantments,
+        &a.unbreakable,
+        &a.price,
+        &a.claimed,
+        &a.tier,
+        &a.category,
+        &a.last_updated,
+        &a.bin,
+        &a.reforge,
+        &a.upgrade_level,
+        &a.hot_potato_count,
+        &a.recomb,
+        &a.unlocked_gem_slots,
+        &a.slotted_gems,
+        &a.pet_active,
+        &a.pet_type,
+        &a.pet_held_item,
+        &a.pet_exp,
+        &a.pet_candy_used,
+        &a.dungeon_item_level,
+        &a.red_armor_coloring,
+        &a.green_armor_coloring,
+        &a.blue_armor_coloring,
+        &a.anvil_uses,
+        &a.pelts_earned,
+        &a.champion_combat_xp,
+        &a.farmed_cultivating,
+        &a.compact_blocks,
+        &a.hecatomb_s_runs,
+        &a.expertise_kills,
+        &a.runes,
+        &a.item_key,
+        &a.highest_bid,
+    ]
+}
+
+/// Writes `auctions` in one transaction by streaming them with a binary `COPY` into a temporary
+/// staging table and merging that into `auctions`, instead of one `INSERT` per chunk. Returns
+/// the rows inserted or updated. Stored auctions get their `refresh` columns overwritten.
+///
+/// diesel can't drive `COPY`, so this goes through a raw `client` rather than the pool.
+pub async fn copy_auctions(
+    client: &mut Client,
+    auctions: &[Auction],
+    refresh: &[&str],
+) -> Result<u64, AHScraperError> {
+    if auctions.is_empty() {
+        return Ok(0);
+    }
+    let columns: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+    let columns = columns.join(", ");
+    let types: Vec<Type> = COLUMNS.iter().map(|(_, ty)| ty.clone()).collect();
+
+    let transaction = client.transaction().await?;
+    transaction
+        .batch_execute(
+            "CREATE TEMP TABLE auctions_staging (LIKE auctions INCLUDING DEFAULTS) ON COMMIT DROP",
+        )
+        .await?;
+    let sink = transaction
+        .copy_in(&format!(
+            "COPY auctions_staging ({}) FROM STDIN (FORMAT binary)",
+            columns
+        ))
+        .await?;
+    let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+    for auction in auctions {
+        let model = AuctionModel::from(auction.clone());
+        writer.as_mut().write(&row(&model)).await?;
+    }
+    writer.as_mut().finish().await?;
+    let on_conflict = if refresh.is_empty() {
+        "DO NOTHING".to_string()
+    } else {
+        let updates: Vec<String> = refresh
+            .iter()
+            .map(|column| format!("{0} = excluded.{0}", column))
+            .collect();
+        format!("DO UPDATE SET {}", updates.join(", "))
+    };
+    // an auction can show up twice when it moves pages mid scan, and ON CONFLICT can't touch
+    // the same row twice in one statement, so only its latest state is merged.
+    let merged = transaction
+        .execute(
+            &format!(
+                "INSERT INTO auctions ({0}) \
+                 SELECT DISTINCT ON (uuid) {0} FROM auctions_staging \
+                 ORDER BY uuid, last_updated DESC \
+                 ON CONFLICT (uuid, start_time) {1}",
+                columns, on_conflict
+            ),
+            &[],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(merged)
+}
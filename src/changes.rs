@@ -0,0 +1,128 @@
+use crate::hypixel_api::auction::Auction;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// The parts of an auction that change while it's listed.
+#[derive(PartialEq, Eq)]
+struct Fingerprint {
+    last_updated: SystemTime,
+    highest_bid_amount: i64,
+    claimed: bool,
+}
+
+impl From<&Auction> for Fingerprint {
+    fn from(auction: &Auction) -> Self {
+        Fingerprint {
+            last_updated: auction.last_updated,
+            highest_bid_amount: auction.highest_bid_amount,
+            claimed: auction.claimed,
+        }
+    }
+}
+
+struct Written {
+    fingerprint: Fingerprint,
+    end: SystemTime,
+}
+
+/// How a batch compared to what was already written.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChangeCounts {
+    pub new: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+/// Remembers what was last written for each auction so a batch can be cut down to the auctions
+/// that are new or changed since.
+#[derive(Default)]
+pub struct ChangeIndex {
+    written: HashMap<String, Written>,
+}
+
+impl ChangeIndex {
+    /// The auctions in `batch` the database doesn't have in this state yet.
+    pub fn diff(&self, batch: &[Auction]) -> (Vec<Auction>, ChangeCounts) {
+        let mut counts = ChangeCounts::default();
+        let changed = batch
+            .iter()
+            .filter(|auction| match self.written.get(&auction.uuid) {
+                None => {
+                    counts.new += 1;
+                    true
+                }
+                Some(written) if written.fingerprint != Fingerprint::from(*auction) => {
+                    counts.updated += 1;
+                    true
+                }
+                Some(_) => {
+                    counts.unchanged += 1;
+                    false
+                }
+            })
+            .cloned()
+            .collect();
+        (changed, counts)
+    }
+
+    /// Remembers `auctions` as written, only once the write went through so a failed one is
+    /// retried on the next batch.
+    pub fn record(&mut self, auctions: &[Auction]) {
+        for auction in auctions {
+            self.written.insert(
+                auction.uuid.clone(),
+                Written {
+                    fingerprint: auction.into(),
+                    end: auction.end,
+                },
+            );
+        }
+    }
+
+    /// Forgets auctions that closed before their end time, bought or cancelled.
+    pub fn forget(&mut self, uuids: &[String]) {
+        for uuid in uuids {
+            self.written.remove(uuid);
+        }
+    }
+
+    /// Forgets auctions that ended before `now`, they won't change again.
+    pub fn prune(&mut self, now: SystemTime) {
+        self.written.retain(|_, written| written.end > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hypixel_api::auction::test_auction;
+    use std::time::Duration;
+
+    #[test]
+    fn diff_skips_what_was_written() {
+        let mut index = ChangeIndex::default();
+        let mut a = test_auction("a", 100, true);
+        let b = test_auction("b", 100, true);
+        index.record(&[a.clone(), b.clone()]);
+        a.highest_bid_amount = 150;
+        let c = test_auction("c", 100, true);
+        let (changed, counts) = index.diff(&[a, b, c]);
+        let uuids: Vec<&str> = changed.iter().map(|a| a.uuid.as_str()).collect();
+        assert_eq!(uuids, ["a", "c"]);
+        assert_eq!((counts.new, counts.updated, counts.unchanged), (1, 1, 1));
+    }
+
+    #[test]
+    fn forgotten_and_pruned_auctions_are_new_again() {
+        let mut index = ChangeIndex::default();
+        let mut a = test_auction("a", 100, true);
+        let b = test_auction("b", 100, true);
+        a.end += Duration::from_secs(60 * 60);
+        index.record(&[a.clone(), b.clone()]);
+        index.forget(&["a".to_string()]);
+        assert_eq!(index.diff(&[a.clone()]).1.new, 1);
+        index.record(&[a.clone()]);
+        index.prune(b.end);
+        assert_eq!(index.diff(&[a, b]).1.new, 1);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::{Config, IngestMode, ScrapeConfig};
+use crate::export::{self, ExportFormat, ExportOptions};
+use crate::upsert::RefreshColumns;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(Parser)]
+#[command(
+    name = "AHScraper",
+    about = "Scrapes the Hypixel SkyBlock auction house"
+)]
+pub struct Cli {
+    /// TOML config file, defaults to ahscraper.toml when it exists.
+    #[arg(long, env = "AH_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+    /// Postgres connection string, overrides the config and DATABASE_URL.
+    #[arg(long, global = true)]
+    pub database_url: Option<String>,
+    /// Defaults to `scrape`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Index every auction then keep scraping until stopped.
+    Scrape(ScanArgs),
+    /// Index every auction once and exit.
+    SyncOnce(UpsertArgs),
+    /// Apply pending database migrations.
+    Migrate,
+    /// Dump stored auctions to NDJSON, CSV or Parquet files.
+    Export(ExportArgs),
+    /// Run the scraper against archived pages instead of the API.
+    Replay(ReplayArgs),
+    /// Answer a question from the database.
+    #[command(subcommand)]
+    Query(QueryCommand),
+    /// Parse every archived page again and write the results to the database.
+    Reprocess(ReprocessArgs),
+    /// Time writing the same auctions with each ingest mode.
+    BenchIngest(BenchIngestArgs),
+    /// Split ended months out of the hot auctions partition and apply the retention once.
+    MaintainPartitions,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Scrape(ScanArgs::default())
+    }
+}
+
+impl Command {
+    /// Applies the command's flags on top of the config, they take precedence over everything.
+    pub fn apply(&self, config: &mut Config) {
+        match self {
+            Command::Scrape(scan) | Command::Replay(ReplayArgs { scan, .. }) => {
+                scan.apply(&mut config.scrape)
+            }
+            Command::SyncOnce(upsert) | Command::Reprocess(ReprocessArgs { upsert, .. }) => {
+                upsert.apply(&mut config.scrape)
+            }
+            Command::BenchIngest(BenchIngestArgs { upsert, .. }) => {
+                upsert.apply(&mut config.scrape)
+            }
+            Command::Migrate
+            | Command::Export(_)
+            | Command::Query(_)
+            | Command::MaintainPartitions => {}
+        }
+    }
+}
+
+#[derive(Args, Clone, Default)]
+pub struct UpsertArgs {
+    /// Auctions written by each upsert task.
+    #[arg(long)]
+    pub chunk_size: Option<usize>,
+    /// chunked or copy.
+    #[arg(long)]
+    pub ingest: Option<IngestMode>,
+    /// Comma separated column groups overwritten on stored auctions: state, listing, item.
+    #[arg(long)]
+    pub refresh_columns: Option<RefreshColumns>,
+}
+
+impl UpsertArgs {
+    fn apply(&self, config: &mut ScrapeConfig) {
+        if let Some(chunk_size) = self.chunk_size {
+            config.chunk_size = chunk_size;
+        }
+        if let Some(ingest) = self.ingest {
+            config.ingest = ingest;
+        }
+        if let Some(refresh_columns) = &self.refresh_columns {
+            config.refresh_columns = refresh_columns.clone();
+        }
+    }
+}
+
+#[derive(Args, Clone, Default)]
+pub struct ScanArgs {
+    #[command(flatten)]
+    pub upsert: UpsertArgs,
+    /// Pages fetched by each quick scan.
+    #[arg(long)]
+    pub pages: Option<u32>,
+    /// Pause after each quick scan, in milliseconds.
+    #[arg(long)]
+    pub tick_interval_ms: Option<u64>,
+    /// Seconds between the starts of full rescans of every page.
+    #[arg(long)]
+    pub full_scan_interval_secs: Option<u64>,
+}
+
+impl ScanArgs {
+    fn apply(&self, config: &mut ScrapeConfig) {
+        self.upsert.apply(config);
+        if let Some(pages) = self.pages {
+            config.pages = pages;
+        }
+        if let Some(tick_interval_ms) = self.tick_interval_ms {
+            config.tick_interval_ms = tick_interval_ms;
+        }
+        if let Some(full_scan_interval_secs) = self.full_scan_interval_secs {
+            config.full_scan_interval_secs = full_scan_interval_secs;
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// ndjson, csv or parquet (with the parquet feature).
+    #[arg(long, default_value = "ndjson")]
+    pub format: ExportFormat,
+    #[arg(long, default_value = "export")]
+    pub out: PathBuf,
+    /// Only auctions that started at or after this date or RFC 3339 time.
+    #[arg(long, value_parser = export::parse_time)]
+    pub since: Option<SystemTime>,
+    /// Only auctions that started before this date or RFC 3339 time.
+    #[arg(long, value_parser = export::parse_time)]
+    pub until: Option<SystemTime>,
+    #[arg(long)]
+    pub item_id: Option<String>,
+    /// Only BIN listings.
+    #[arg(long, conflicts_with = "auctions_only")]
+    pub bin: bool,
+    /// Only regular auctions.
+    #[arg(long)]
+    pub auctions_only: bool,
+    /// Write one file per day of start time instead of a single file.
+    #[arg(long)]
+    pub partition_by_day: bool,
+}
+
+impl From<ExportArgs> for ExportOptions {
+    fn from(args: ExportArgs) -> Self {
+        ExportOptions {
+            format: args.format,
+            out_dir: args.out,
+            since: args.since,
+            until: args.until,
+            item_id: args.item_id,
+            bin: match (args.bin, args.auctions_only) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            partition_by_day: args.partition_by_day,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// Archive directory written by the scraper with ARCHIVE_DIR set.
+    pub dir: PathBuf,
+    /// How much faster than recorded to replay, 0 moves to the next snapshot every scan.
+    #[arg(long, default_value_t = 0.0)]
+    pub speed: f64,
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Subcommand)]
+pub enum QueryCommand {
+    /// Cheapest open BIN listing of an item key.
+    LowestBin { item_key: String },
+    /// Open auctions of an item, cheapest first.
+    Auctions {
+        item_key: String,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Open auctions of an item id whatever their upgrades, cheapest first. Postgres only, like
+    /// the rest below.
+    ItemId {
+        item_id: String,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// A player's listings, most recently updated first.
+    Seller {
+        auctioneer: String,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Every auction an individual item (by item uuid) was listed in.
+    History { item_uuid: String },
+    /// Open auctions ending in the next few minutes.
+    EndingSoon {
+        #[arg(long, default_value_t = 5)]
+        minutes: u64,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Open auctions with an enchantment (e.g. ultimate_wise), cheapest first.
+    Enchantment {
+        enchantment: String,
+        #[arg(long, default_value_t = 1)]
+        min_level: u32,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Open auctions with a rune (e.g. MUSIC), cheapest first.
+    Rune {
+        rune: String,
+        #[arg(long, default_value_t = 1)]
+        min_level: u32,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+}
+
+#[derive(Args)]
+pub struct ReprocessArgs {
+    /// Archive directory to read pages from, defaults to the configured archive.
+    #[arg(long)]
+    pub archive: Option<PathBuf>,
+    #[command(flatten)]
+    pub upsert: UpsertArgs,
+}
+
+#[derive(Args)]
+pub struct BenchIngestArgs {
+    /// Write the newest snapshot in this archive, defaults to the configured archive and then to
+    /// fetching every auction from the API.
+    #[arg(long)]
+    pub archive: Option<PathBuf>,
+    /// Times each mode writes the auctions, the first round inserts and the rest update.
+    #[arg(long, default_value_t = 3)]
+    pub rounds: u32,
+    #[command(flatten)]
+    pub upsert: UpsertArgs,
+}
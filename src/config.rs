@@ -0,0 +1,617 @@
+use crate::archive::{RetentionPolicy, SnapshotArchiver};
+use crate::flips::FlipConfig;
+use crate::hypixel_api::{HypixelClient, DEFAULT_BASE_URL};
+use crate::models::NewSavedSearch;
+use crate::partitions::ExpiredPartitions;
+use crate::upsert::RefreshColumns;
+use crate::AHScraperError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs};
+
+/// Read when `--config` isn't given, if it exists.
+pub const DEFAULT_PATH: &str = "ahscraper.toml";
+
+/// Everything the scraper can be tuned with. Values come from the defaults below, then the TOML
+/// file, then environment variables, then command line flags.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub hypixel: HypixelConfig,
+    pub database: DatabaseConfig,
+    pub scrape: ScrapeConfig,
+    pub jobs: JobsConfig,
+    pub archive: ArchiveConfig,
+    pub alerts: AlertsConfig,
+    pub server: ServerConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HypixelConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+}
+
+impl Default for HypixelConfig {
+    fn default() -> Self {
+        HypixelConfig {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    /// Connections held at most, counting the one copy ingest writes through.
+    pub pool_size: u32,
+    /// Apply pending migrations before commands that write auctions.
+    pub auto_migrate: bool,
+    /// Months of auctions, by month listed, kept before the current one, every month when unset.
+    pub retention_months: Option<u32>,
+    /// What happens to months past `retention_months`.
+    pub expired_partitions: ExpiredPartitions,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: None,
+            pool_size: 10,
+            auto_migrate: true,
+            retention_months: None,
+            expired_partitions: ExpiredPartitions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrapeConfig {
+    /// Pages fetched by each quick scan, new listings land on the first pages.
+    pub pages: u32,
+    /// Auctions written by each upsert task.
+    pub chunk_size: usize,
+    pub ingest: IngestMode,
+    /// Column groups (state, listing, item) overwritten when a stored auction is seen again.
+    /// Full rescans and reprocessing always overwrite every column.
+    pub refresh_columns: RefreshColumns,
+    /// Pause after each quick scan.
+    pub tick_interval_ms: u64,
+    /// Time between the starts of full rescans of every page, which catch what quick scans miss.
+    pub full_scan_interval_secs: u64,
+    pub bazaar_interval_secs: u64,
+    pub ended_interval_secs: u64,
+    /// How long shutdown waits for in-flight writes before giving up on them.
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ScrapeConfig {
+    fn default() -> Self {
+        ScrapeConfig {
+            pages: 10,
+            chunk_size: 100,
+            ingest: IngestMode::default(),
+            refresh_columns: RefreshColumns::default(),
+            tick_interval_ms: 500,
+            full_scan_interval_secs: 3600,
+            bazaar_interval_secs: 60,
+            // auctions_ended only refreshes once a minute.
+            ended_interval_secs: 20,
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
+/// How batches of auctions are written to the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestMode {
+    /// Concurrent `INSERT ... ON CONFLICT` tasks of `chunk_size` auctions each.
+    #[default]
+    Chunked,
+    /// One `COPY` into a staging table merged with a single statement, far fewer round trips
+    /// for full scans.
+    Copy,
+}
+
+impl FromStr for IngestMode {
+    type Err = AHScraperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunked" => Ok(IngestMode::Chunked),
+            "copy" => Ok(IngestMode::Copy),
+            _ => Err(AHScraperError::InvalidArgument(format!(
+                "unknown ingest mode {} (expected chunked or copy)",
+                s
+            ))),
+        }
+    }
+}
+
+impl ScrapeConfig {
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_millis(self.tick_interval_ms)
+    }
+
+    pub fn full_scan_interval(&self) -> Duration {
+        Duration::from_secs(self.full_scan_interval_secs)
+    }
+
+    pub fn bazaar_interval(&self) -> Duration {
+        Duration::from_secs(self.bazaar_interval_secs)
+    }
+
+    pub fn ended_interval(&self) -> Duration {
+        Duration::from_secs(self.ended_interval_secs)
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+}
+
+/// Background work that can be switched off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Bazaar prices for valuing upgrades, without them upgrades are valued from the AH alone.
+    pub bazaar: bool,
+    /// Sold auctions for events.
+    pub ended_auctions: bool,
+    /// Market analysis and seller stats.
+    pub analytics: bool,
+    pub analysis_interval_secs: u64,
+    pub sellers_interval_secs: u64,
+    /// Splitting ended months out of the hot auctions partition and applying the retention.
+    pub partitions: bool,
+    pub partitions_interval_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            bazaar: true,
+            ended_auctions: true,
+            analytics: true,
+            analysis_interval_secs: 5 * 60,
+            sellers_interval_secs: 15 * 60,
+            partitions: true,
+            partitions_interval_secs: 6 * 60 * 60,
+        }
+    }
+}
+
+/// Raw page archiving, off unless `dir` is set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub dir: Option<PathBuf>,
+    pub max_age_hours: Option<u64>,
+    pub max_snapshots: Option<usize>,
+    pub max_bytes: Option<u64>,
+}
+
+impl ArchiveConfig {
+    pub fn archiver(&self) -> Option<SnapshotArchiver> {
+        let retention = RetentionPolicy {
+            max_age: self
+                .max_age_hours
+                .map(|hours| Duration::from_secs(hours * 60 * 60)),
+            max_snapshots: self.max_snapshots,
+            max_bytes: self.max_bytes,
+        };
+        Some(SnapshotArchiver::new(self.dir.clone()?, retention))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// Flip thresholds, see [`FlipConfig`].
+    pub min_profit: i64,
+    pub min_margin: f64,
+    pub min_confidence: f64,
+    /// Saved searches kept in sync with the config on startup.
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        let flips = FlipConfig::default();
+        AlertsConfig {
+            min_profit: flips.min_profit,
+            min_margin: flips.min_margin,
+            min_confidence: flips.min_confidence,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl AlertsConfig {
+    pub fn flip_config(&self) -> FlipConfig {
+        FlipConfig {
+            min_profit: self.min_profit,
+            min_margin: self.min_margin,
+            min_confidence: self.min_confidence,
+            ..FlipConfig::default()
+        }
+    }
+}
+
+/// A saved search defined in the config file, matched by name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub webhook_url: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub name_contains: Option<String>,
+    pub tier: Option<String>,
+    pub min_stars: Option<i32>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
+    #[serde(default = "default_bin_only")]
+    pub bin_only: bool,
+    /// `json` or `discord`.
+    #[serde(default = "default_format")]
+    pub format: String,
+}
+
+fn default_bin_only() -> bool {
+    true
+}
+
+fn default_format() -> String {
+    "json".to_string()
+}
+
+impl From<&AlertRule> for NewSavedSearch {
+    fn from(rule: &AlertRule) -> Self {
+        NewSavedSearch {
+            name: rule.name.clone(),
+            webhook_url: rule.webhook_url.clone(),
+            item_id: rule.item_id.clone(),
+            item_key: rule.item_key.clone(),
+            name_contains: rule.name_contains.clone(),
+            tier: rule.tier.clone(),
+            min_stars: rule.min_stars,
+            min_price: rule.min_price,
+            max_price: rule.max_price,
+            bin_only: rule.bin_only,
+            payload_format: rule.format.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the query API listens on, when built with the api feature.
+    pub addr: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: "0.0.0.0:3000".to_string(),
+        }
+    }
+}
+
+fn env_value<T: FromStr>(name: &str) -> Result<Option<T>, AHScraperError> {
+    match env::var(name) {
+        Ok(value) => value.parse().map(Some).map_err(|_| {
+            AHScraperError::Config(format!("{} has an invalid value: {}", name, value))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_override<T: FromStr>(name: &str, target: &mut T) -> Result<(), AHScraperError> {
+    if let Some(value) = env_value(name)? {
+        *target = value;
+    }
+    Ok(())
+}
+
+fn env_override_option<T: FromStr>(
+    name: &str,
+    target: &mut Option<T>,
+) -> Result<(), AHScraperError> {
+    if let Some(value) = env_value(name)? {
+        *target = Some(value);
+    }
+    Ok(())
+}
+
+fn http_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+impl Config {
+    /// Loads `path`, or [`DEFAULT_PATH`] if it exists when no path is given, and applies
+    /// environment overrides on top.
+    pub fn load(path: Option<&Path>) -> Result<Config, AHScraperError> {
+        let mut config = match path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Config::from_file(Path::new(DEFAULT_PATH))?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, AHScraperError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            AHScraperError::Config(format!("could not read {}: {}", path.display(), e))
+        })?;
+        toml::from_str(&contents)
+            .map_err(|e| AHScraperError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    fn apply_env(&mut self) -> Result<(), AHScraperError> {
+        env_override("AH_HYPIXEL_BASE_URL", &mut self.hypixel.base_url)?;
+        env_override_option("HYPIXEL_API_KEY", &mut self.hypixel.api_key)?;
+        env_override_option("DATABASE_URL", &mut self.database.url)?;
+        env_override("AH_DB_POOL_SIZE", &mut self.database.pool_size)?;
+        env_override("AH_DB_AUTO_MIGRATE", &mut self.database.auto_migrate)?;
+        env_override_option(
+            "AH_DB_RETENTION_MONTHS",
+            &mut self.database.retention_months,
+        )?;
+        env_override(
+            "AH_DB_EXPIRED_PARTITIONS",
+            &mut self.database.expired_partitions,
+        )?;
+        env_override("AH_SCRAPE_PAGES", &mut self.scrape.pages)?;
+        env_override("AH_SCRAPE_CHUNK_SIZE", &mut self.scrape.chunk_size)?;
+        env_override("AH_SCRAPE_INGEST", &mut self.scrape.ingest)?;
+        env_override(
+            "AH_SCRAPE_REFRESH_COLUMNS",
+            &mut self.scrape.refresh_columns,
+        )?;
+        env_override(
+            "AH_SCRAPE_TICK_INTERVAL_MS",
+            &mut self.scrape.tick_interval_ms,
+        )?;
+        env_override(
+            "AH_SCRAPE_FULL_SCAN_INTERVAL_SECS",
+            &mut self.scrape.full_scan_interval_secs,
+        )?;
+        env_override(
+            "AH_SCRAPE_BAZAAR_INTERVAL_SECS",
+            &mut self.scrape.bazaar_interval_secs,
+        )?;
+        env_override(
+            "AH_SCRAPE_ENDED_INTERVAL_SECS",
+            &mut self.scrape.ended_interval_secs,
+        )?;
+        env_override(
+            "AH_SCRAPE_DRAIN_TIMEOUT_SECS",
+            &mut self.scrape.drain_timeout_secs,
+        )?;
+        env_override("AH_JOBS_BAZAAR", &mut self.jobs.bazaar)?;
+        env_override("AH_JOBS_ENDED_AUCTIONS", &mut self.jobs.ended_auctions)?;
+        env_override("AH_JOBS_ANALYTICS", &mut self.jobs.analytics)?;
+        env_override(
+            "AH_JOBS_ANALYSIS_INTERVAL_SECS",
+            &mut self.jobs.analysis_interval_secs,
+        )?;
+        env_override(
+            "AH_JOBS_SELLERS_INTERVAL_SECS",
+            &mut self.jobs.sellers_interval_secs,
+        )?;
+        env_override("AH_JOBS_PARTITIONS", &mut self.jobs.partitions)?;
+        env_override(
+            "AH_JOBS_PARTITIONS_INTERVAL_SECS",
+            &mut self.jobs.partitions_interval_secs,
+        )?;
+        env_override("AH_ALERTS_MIN_PROFIT", &mut self.alerts.min_profit)?;
+        env_override("AH_ALERTS_MIN_MARGIN", &mut self.alerts.min_margin)?;
+        env_override("AH_ALERTS_MIN_CONFIDENCE", &mut self.alerts.min_confidence)?;
+        env_override_option("ARCHIVE_DIR", &mut self.archive.dir)?;
+        env_override_option("ARCHIVE_MAX_AGE_HOURS", &mut self.archive.max_age_hours)?;
+        env_override_option("ARCHIVE_MAX_SNAPSHOTS", &mut self.archive.max_snapshots)?;
+        env_override_option("ARCHIVE_MAX_BYTES", &mut self.archive.max_bytes)?;
+        env_override("API_ADDR", &mut self.server.addr)?;
+        Ok(())
+    }
+
+    /// Checks everything that would otherwise only fail once the scraper is running.
+    pub fn validate(&self) -> Result<(), AHScraperError> {
+        let mut problems = Vec::new();
+        if !http_url(&self.hypixel.base_url) {
+            problems.push(format!(
+                "hypixel.base_url must be an http(s) URL, got {}",
+                self.hypixel.base_url
+            ));
+        }
+        match &self.database.url {
+            None => problems.push(
+                "database.url is not set (or set DATABASE_URL or pass --database-url)".to_string(),
+            ),
+            Some(url) if crate::store::sqlite_path(url).is_some() => {
+                if !cfg!(feature = "sqlite") {
+                    problems.push(
+                        "database.url is a SQLite database but the sqlite feature is off"
+                            .to_string(),
+                    )
+                }
+            }
+            Some(url) if !url.starts_with("postgres://") && !url.starts_with("postgresql://") => {
+                problems.push("database.url must be a postgres:// or sqlite: URL".to_string())
+            }
+            Some(_) => {}
+        }
+        if self.database.pool_size == 0 {
+            problems.push("database.pool_size must be at least 1".to_string());
+        } else if self.database.pool_size == 1 && self.scrape.ingest == IngestMode::Copy {
+            problems.push("database.pool_size must be at least 2 with copy ingest".to_string());
+        }
+        if self.scrape.pages == 0 {
+            problems.push("scrape.pages must be at least 1".to_string());
+        }
+        if self.scrape.chunk_size == 0 {
+            problems.push("scrape.chunk_size must be at least 1".to_string());
+        }
+        for (name, secs) in [
+            (
+                "scrape.full_scan_interval_secs",
+                self.scrape.full_scan_interval_secs,
+            ),
+            (
+                "scrape.bazaar_interval_secs",
+                self.scrape.bazaar_interval_secs,
+            ),
+            (
+                "scrape.ended_interval_secs",
+                self.scrape.ended_interval_secs,
+            ),
+            (
+                "jobs.analysis_interval_secs",
+                self.jobs.analysis_interval_secs,
+            ),
+            (
+                "jobs.sellers_interval_secs",
+                self.jobs.sellers_interval_secs,
+            ),
+            (
+                "jobs.partitions_interval_secs",
+                self.jobs.partitions_interval_secs,
+            ),
+        ] {
+            if secs == 0 {
+                problems.push(format!("{} must be at least 1", name));
+            }
+        }
+        if self.alerts.min_margin < 0.0 {
+            problems.push("alerts.min_margin can't be negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.alerts.min_confidence) {
+            problems.push("alerts.min_confidence must be between 0 and 1".to_string());
+        }
+        for (i, rule) in self.alerts.rules.iter().enumerate() {
+            let label = format!("alerts.rules[{}] ({})", i, rule.name);
+            if rule.name.trim().is_empty() {
+                problems.push(format!("alerts.rules[{}] needs a name", i));
+            }
+            if !http_url(&rule.webhook_url) {
+                problems.push(format!("{} webhook_url must be an http(s) URL", label));
+            }
+            if rule.format != "json" && rule.format != "discord" {
+                problems.push(format!(
+                    "{} format must be json or discord, got {}",
+                    label, rule.format
+                ));
+            }
+            if let (Some(min), Some(max)) = (rule.min_price, rule.max_price) {
+                if min > max {
+                    problems.push(format!("{} min_price is above max_price", label));
+                }
+            }
+            if self.alerts.rules[..i].iter().any(|r| r.name == rule.name) {
+                problems.push(format!("{} has the same name as an earlier rule", label));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AHScraperError::Config(format!(
+                "\n  {}",
+                problems.join("\n  ")
+            )))
+        }
+    }
+
+    pub fn database_url(&self) -> &str {
+        self.database.url.as_deref().unwrap_or_default()
+    }
+
+    pub fn hypixel_client(&self) -> HypixelClient {
+        HypixelClient::new(&self.hypixel.base_url, self.hypixel.api_key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(config: &Config) -> String {
+        match config.validate() {
+            Err(AHScraperError::Config(problems)) => problems,
+            other => panic!("expected config problems, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn example_config_is_the_defaults() {
+        let example: Config = toml::from_str(include_str!("../ahscraper.example.toml")).unwrap();
+        let defaults = Config::default();
+        assert_eq!(example.hypixel.base_url, defaults.hypixel.base_url);
+        assert_eq!(example.database.pool_size, defaults.database.pool_size);
+        assert_eq!(
+            example.scrape.refresh_columns,
+            defaults.scrape.refresh_columns
+        );
+        assert_eq!(
+            example.scrape.ended_interval(),
+            defaults.scrape.ended_interval()
+        );
+        assert_eq!(
+            example.jobs.partitions_interval_secs,
+            defaults.jobs.partitions_interval_secs
+        );
+        assert_eq!(example.alerts.min_profit, defaults.alerts.min_profit);
+        // the example leaves the database url to the environment.
+        assert!(problems(&example).contains("database.url is not set"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let error = toml::from_str::<Config>("[scrape]\npagez = 3\n").unwrap_err();
+        assert!(error.to_string().contains("pagez"));
+        assert!(toml::from_str::<Config>("[scraper]\npages = 3\n").is_err());
+    }
+
+    #[test]
+    fn environment_overrides_the_file_then_validation_applies() {
+        let path = env::temp_dir().join(format!("ahscraper-test-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[database]\nurl = \"postgres://localhost/ah\"\npool_size = 1\n\
+             [scrape]\npages = 3\ningest = \"copy\"\nbazaar_interval_secs = 30\n",
+        )
+        .unwrap();
+        env::remove_var("DATABASE_URL");
+        env::set_var("AH_SCRAPE_BAZAAR_INTERVAL_SECS", "45");
+        env::set_var("AH_JOBS_SELLERS_INTERVAL_SECS", "0");
+        env::set_var("AH_ALERTS_MIN_CONFIDENCE", "1.5");
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.scrape.pages, 3);
+        assert_eq!(config.scrape.bazaar_interval(), Duration::from_secs(45));
+        assert_eq!(config.jobs.sellers_interval_secs, 0);
+        let problems = problems(&config);
+        assert!(problems.contains("jobs.sellers_interval_secs must be at least 1"));
+        assert!(problems.contains("alerts.min_confidence must be between 0 and 1"));
+        assert!(problems.contains("pool_size must be at least 2 with copy ingest"));
+        assert!(!problems.contains("database.url"));
+        env::set_var("AH_SCRAPE_BAZAAR_INTERVAL_SECS", "soon");
+        assert!(Config::load(Some(&path)).is_err());
+        for name in [
+            "AH_SCRAPE_BAZAAR_INTERVAL_SECS",
+            "AH_JOBS_SELLERS_INTERVAL_SECS",
+            "AH_ALERTS_MIN_CONFIDENCE",
+        ] {
+            env::remove_var(name);
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
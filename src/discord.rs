@@ -0,0 +1,175 @@
+use crate::models::Auction as AuctionModel;
+use serde::Serialize;
+
+/// Body of a Discord webhook execute request.
+#[derive(Serialize, Debug)]
+pub struct WebhookMessage {
+    pub username: String,
+    pub embeds: Vec<Embed>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Embed {
+    pub title: String,
+    pub description: String,
+    pub color: u32,
+    pub fields: Vec<EmbedField>,
+    pub footer: EmbedFooter,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EmbedFooter {
+    pub text: String,
+}
+
+/// Discord has a hard limit of 1024 characters per field value.
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// The in game color of a rarity, as an embed color.
+pub fn tier_color(tier: &str) -> u32 {
+    match tier {
+        "COMMON" => 0xFFFFFF,
+        "UNCOMMON" => 0x55FF55,
+        "RARE" => 0x5555FF,
+        "EPIC" => 0xAA00AA,
+        "LEGENDARY" => 0xFFAA00,
+        "MYTHIC" => 0xFF55FF,
+        "DIVINE" => 0x55FFFF,
+        "SPECIAL" | "VERY_SPECIAL" => 0xFF5555,
+        _ => 0xAAAAAA,
+    }
+}
+
+/// Shortens a coin amount the way players write it, 38500000 becomes 38.5M.
+pub fn format_coins(coins: i64) -> String {
+    let abs = coins.unsigned_abs() as f64;
+    let sign = if coins < 0 { "-" } else { "" };
+    let (value, suffix) = if abs >= 1e9 {
+        (abs / 1e9, "B")
+    } else if abs >= 1e6 {
+        (abs / 1e6, "M")
+    } else if abs >= 1e3 {
+        (abs / 1e3, "k")
+    } else {
+        return coins.to_string();
+    };
+    let formatted = format!("{:.2}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}{}", sign, formatted, suffix)
+}
+
+fn list_field(name: &str, values: Option<&Vec<String>>) -> Option<EmbedField> {
+    let values = values.filter(|v| !v.is_empty())?;
+    let mut value = values.join(", ");
+    if value.len() > MAX_FIELD_LENGTH {
+        let mut end = MAX_FIELD_LENGTH - 3;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+        value.push_str("...");
+    }
+    Some(EmbedField {
+        name: name.to_string(),
+        value,
+        inline: false,
+    })
+}
+
+/// Renders an auction into a webhook message with a single embed. `title` is what triggered the
+/// alert (e.g. the saved search name) and `lowest_bin` is used to show how far under the floor the
+/// listing is.
+pub fn auction_message(
+    title: &str,
+    auction: &AuctionModel,
+    lowest_bin: Option<i64>,
+) -> WebhookMessage {
+    let mut fields = vec![
+        EmbedField {
+            name: "Price".to_string(),
+            value: format_coins(auction.price),
+            inline: true,
+        },
+        EmbedField {
+            name: "Type".to_string(),
+            value: if auction.bin { "BIN" } else { "Auction" }.to_string(),
+            inline: true,
+        },
+    ];
+    if let Some(lowest_bin) = lowest_bin {
+        let delta = auction.price - lowest_bin;
+        let percent = if lowest_bin > 0 {
+            delta as f64 / lowest_bin as f64 * 100.0
+        } else {
+            0.0
+        };
+        fields.push(EmbedField {
+            name: "Lowest BIN".to_string(),
+            value: format!(
+                "{} ({}{} / {:+.1}%)",
+                format_coins(lowest_bin),
+                if delta >= 0 { "+" } else { "" },
+                format_coins(delta),
+                percent
+            ),
+            inline: true,
+        });
+    }
+    fields.extend(list_field("Enchantments", auction.enchantments.as_ref()));
+    fields.extend(list_field("Gems", auction.slotted_gems.as_ref()));
+    fields.push(EmbedField {
+        name: "View".to_string(),
+        value: format!("`/viewauction {}`", auction.uuid),
+        inline: false,
+    });
+    WebhookMessage {
+        username: "AH Scraper".to_string(),
+        embeds: vec![Embed {
+            title: auction.item_name.clone(),
+            description: format!("**{}** · {}", title, auction.tier.replace('_', " ")),
+            color: tier_color(&auction.tier),
+            fields,
+            footer: EmbedFooter {
+                text: auction.item_key.clone().unwrap_or_default(),
+            },
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coins_shorten_like_players_write_them() {
+        assert_eq!(format_coins(999), "999");
+        assert_eq!(format_coins(1_000), "1k");
+        assert_eq!(format_coins(38_500_000), "38.5M");
+        assert_eq!(format_coins(1_234_567_890), "1.23B");
+        assert_eq!(format_coins(-2_500), "-2.5k");
+        assert_eq!(format_coins(-5), "-5");
+    }
+
+    #[test]
+    fn tiers_get_their_in_game_colors() {
+        assert_eq!(tier_color("LEGENDARY"), 0xFFAA00);
+        assert_eq!(tier_color("VERY_SPECIAL"), tier_color("SPECIAL"));
+        assert_eq!(tier_color("legendary"), 0xAAAAAA);
+    }
+
+    #[test]
+    fn long_lists_are_cut_at_the_field_limit() {
+        assert!(list_field("Enchantments", Some(&Vec::new())).is_none());
+        let values = vec!["é".repeat(400); 3];
+        let field = list_field("Enchantments", Some(&values)).unwrap();
+        assert!(field.value.len() <= MAX_FIELD_LENGTH);
+        assert!(field.value.ends_with("..."));
+    }
+}
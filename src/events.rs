@@ -0,0 +1,325 @@
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::ended::EndedAuction;
+use crate::models;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+
+/// How many events a slow subscriber can fall behind before it starts missing them.
+pub const EVENT_BUFFER: usize = 4096;
+
+pub type EventSender = broadcast::Sender<AuctionEvent>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum EventKind {
+    NewAuction,
+    PriceChanged { previous_price: i64 },
+    AuctionEnded,
+    AuctionSold { buyer: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuctionEvent {
+    #[serde(flatten)]
+    pub kind: EventKind,
+    pub uuid: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub item_name: Option<String>,
+    pub bin: bool,
+    /// Current price, the highest bid for auctions that have one.
+    pub price: i64,
+    /// Set on new listings the flip finder picked up.
+    pub expected_profit: Option<i64>,
+}
+
+/// Server side filter for event subscribers, every set field has to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EventFilter {
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub max_price: Option<i64>,
+    pub min_profit: Option<i64>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &AuctionEvent) -> bool {
+        if self.item_id.is_some() && self.item_id != event.item_id {
+            return false;
+        }
+        if self.item_key.is_some() && self.item_key != event.item_key {
+            return false;
+        }
+        if self.max_price.is_some_and(|max| event.price > max) {
+            return false;
+        }
+        match self.min_profit {
+            Some(min) => event.expected_profit.is_some_and(|profit| profit >= min),
+            None => true,
+        }
+    }
+}
+
+struct TrackedAuction {
+    item_id: Option<String>,
+    item_key: Option<String>,
+    item_name: String,
+    bin: bool,
+    price: i64,
+    start: SystemTime,
+    end: SystemTime,
+}
+
+impl TrackedAuction {
+    fn event(&self, kind: EventKind, uuid: String) -> AuctionEvent {
+        AuctionEvent {
+            kind,
+            uuid,
+            item_id: self.item_id.clone(),
+            item_key: self.item_key.clone(),
+            item_name: Some(self.item_name.clone()),
+            bin: self.bin,
+            price: self.price,
+            expected_profit: None,
+        }
+    }
+}
+
+fn current_price(auction: &Auction) -> i64 {
+    if auction.highest_bid_amount > 0 {
+        auction.highest_bid_amount
+    } else {
+        auction.starting_bid
+    }
+}
+
+/// Remembers every live auction the scraper has seen so each batch can be turned into events.
+#[derive(Default)]
+pub struct AuctionTracker {
+    auctions: HashMap<String, TrackedAuction>,
+}
+
+impl AuctionTracker {
+    pub fn is_tracked(&self, uuid: &str) -> bool {
+        self.auctions.contains_key(uuid)
+    }
+
+    /// Records a batch from the API, returning new listings and bid changes.
+    pub fn observe(&mut self, auctions: &[Auction]) -> Vec<AuctionEvent> {
+        let mut events = Vec::new();
+        for auction in auctions {
+            let price = current_price(auction);
+            match self.auctions.get_mut(&auction.uuid) {
+                Some(tracked) => {
+                    if tracked.price != price {
+                        let previous_price = tracked.price;
+                        tracked.price = price;
+                        events.push(tracked.event(
+                            EventKind::PriceChanged { previous_price },
+                            auction.uuid.clone(),
+                        ));
+                    }
+                    tracked.end = auction.end;
+                }
+                None => {
+                    let tracked = TrackedAuction {
+                        item_id: auction
+                            .item_data
+                            .as_ref()
+                            .map(|item| item.tag.extra_attributes.id.clone()),
+                        item_key: models::item_key_for(auction),
+                        item_name: auction.item_name.clone(),
+                        bin: auction.bin,
+                        price,
+                        start: auction.start,
+                        end: auction.end,
+                    };
+                    events.push(tracked.event(EventKind::NewAuction, auction.uuid.clone()));
+                    self.auctions.insert(auction.uuid.clone(), tracked);
+                }
+            }
+        }
+        events
+    }
+
+    /// Stops tracking auctions from the ended endpoint, returning a sale for each.
+    pub fn sold(&mut self, ended: &[EndedAuction]) -> Vec<AuctionEvent> {
+        ended
+            .iter()
+            .filter_map(|sale| {
+                let tracked = self.auctions.remove(&sale.auction_id)?;
+                let mut event = tracked.event(
+                    EventKind::AuctionSold {
+                        buyer: sale.buyer.clone(),
+                    },
+                    sale.auction_id.clone(),
+                );
+                event.price = sale.price;
+                Some(event)
+            })
+            .collect()
+    }
+
+    /// Stops tracking auctions that started before `scan_started` but aren't in `seen`, a complete
+    /// scan of every page from one refresh, so they were bought or cancelled without the ended
+    /// endpoint saying so.
+    pub fn close_missing(
+        &mut self,
+        seen: &[Auction],
+        scan_started: SystemTime,
+    ) -> Vec<AuctionEvent> {
+        let seen: HashSet<&str> = seen.iter().map(|a| a.uuid.as_str()).collect();
+        let missing: Vec<String> = self
+            .auctions
+            .iter()
+            .filter(|(uuid, tracked)| tracked.start < scan_started && !seen.contains(uuid.as_str()))
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+        missing
+            .into_iter()
+            .filter_map(|uuid| {
+                let tracked = self.auctions.remove(&uuid)?;
+                Some(tracked.event(EventKind::AuctionEnded, uuid))
+            })
+            .collect()
+    }
+
+    /// Stops tracking auctions past their end time that nobody reported as sold.
+    pub fn expire(&mut self, now: SystemTime) -> Vec<AuctionEvent> {
+        let expired: Vec<String> = self
+            .auctions
+            .iter()
+            .filter(|(_, tracked)| tracked.end <= now)
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|uuid| {
+                let tracked = self.auctions.remove(&uuid)?;
+                Some(tracked.event(EventKind::AuctionEnded, uuid))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hypixel_api::auction::test_auction;
+    use std::time::Duration;
+
+    fn sale(uuid: &str, price: i64) -> EndedAuction {
+        EndedAuction {
+            auction_id: uuid.to_string(),
+            seller: "seller".to_string(),
+            seller_profile: "profile".to_string(),
+            buyer: "buyer".to_string(),
+            timestamp: SystemTime::UNIX_EPOCH,
+            price,
+            bin: true,
+            item_data: None,
+        }
+    }
+
+    fn event(price: i64, expected_profit: Option<i64>) -> AuctionEvent {
+        AuctionEvent {
+            kind: EventKind::NewAuction,
+            uuid: "a".to_string(),
+            item_id: Some("HYPERION".to_string()),
+            item_key: Some("HYPERION".to_string()),
+            item_name: None,
+            bin: true,
+            price,
+            expected_profit,
+        }
+    }
+
+    #[test]
+    fn price_changes_only_on_a_new_bid() {
+        let mut tracker = AuctionTracker::default();
+        let mut auction = test_auction("a", 100, false);
+        let events = tracker.observe(&[auction.clone()]);
+        assert!(matches!(
+            events[..],
+            [AuctionEvent {
+                kind: EventKind::NewAuction,
+                ..
+            }]
+        ));
+        // a later end time alone isn't a price change.
+        auction.end += Duration::from_secs(60);
+        assert!(tracker.observe(&[auction.clone()]).is_empty());
+        auction.highest_bid_amount = 150;
+        let events = tracker.observe(&[auction.clone()]);
+        assert!(matches!(
+            events[..],
+            [AuctionEvent {
+                kind: EventKind::PriceChanged {
+                    previous_price: 100
+                },
+                price: 150,
+                ..
+            }]
+        ));
+        assert!(tracker.observe(&[auction]).is_empty());
+    }
+
+    #[test]
+    fn close_missing_spares_auctions_listed_after_the_scan_started() {
+        let mut tracker = AuctionTracker::default();
+        let seen = test_auction("seen", 100, true);
+        let gone = test_auction("gone", 100, true);
+        let mut listed_later = test_auction("later", 100, true);
+        listed_later.start += Duration::from_secs(60);
+        tracker.observe(&[seen.clone(), gone, listed_later]);
+        let scan_started = seen.start + Duration::from_secs(30);
+        let events = tracker.close_missing(&[seen], scan_started);
+        let uuids: Vec<&str> = events.iter().map(|e| e.uuid.as_str()).collect();
+        assert_eq!(uuids, ["gone"]);
+        assert!(matches!(events[0].kind, EventKind::AuctionEnded));
+        assert!(tracker.is_tracked("seen") && tracker.is_tracked("later"));
+    }
+
+    #[test]
+    fn sold_overrides_the_price_and_expire_ends_the_rest() {
+        let mut tracker = AuctionTracker::default();
+        let auction = test_auction("a", 100, true);
+        let end = auction.end;
+        tracker.observe(&[auction, test_auction("b", 100, true)]);
+        let events = tracker.sold(&[sale("a", 90), sale("untracked", 10)]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].price, 90);
+        assert!(matches!(&events[0].kind, EventKind::AuctionSold { buyer } if buyer == "buyer"));
+        assert!(tracker.expire(end - Duration::from_secs(1)).is_empty());
+        let events = tracker.expire(end);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].uuid, "b");
+        assert!(!tracker.is_tracked("a") && !tracker.is_tracked("b"));
+    }
+
+    #[test]
+    fn filter_needs_every_set_field_to_match() {
+        assert!(EventFilter::default().matches(&event(100, None)));
+        let filter = EventFilter {
+            item_key: Some("HYPERION".to_string()),
+            max_price: Some(100),
+            ..EventFilter::default()
+        };
+        assert!(filter.matches(&event(100, None)));
+        assert!(!filter.matches(&event(101, None)));
+        let filter = EventFilter {
+            item_id: Some("TERMINATOR".to_string()),
+            ..EventFilter::default()
+        };
+        assert!(!filter.matches(&event(100, None)));
+        let filter = EventFilter {
+            min_profit: Some(1_000),
+            ..EventFilter::default()
+        };
+        assert!(!filter.matches(&event(100, None)));
+        assert!(!filter.matches(&event(100, Some(999))));
+        assert!(filter.matches(&event(100, Some(1_000))));
+    }
+}
@@ -0,0 +1,529 @@
+use crate::models::AuctionRecord;
+use crate::schema::auctions;
+use crate::AHScraperError;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use futures::TryStreamExt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = AHScraperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" | "json" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(AHScraperError::InvalidArgument(format!(
+                "unknown export format {} (expected ndjson, csv or parquet, parquet needs the \
+                 parquet feature)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Which auctions to export and where to.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub out_dir: PathBuf,
+    /// Only auctions that started at or after this.
+    pub since: Option<SystemTime>,
+    /// Only auctions that started before this.
+    pub until: Option<SystemTime>,
+    pub item_id: Option<String>,
+    pub bin: Option<bool>,
+    /// Write one file per day of start_time instead of a single file.
+    pub partition_by_day: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            format: ExportFormat::Ndjson,
+            out_dir: PathBuf::from("export"),
+            since: None,
+            until: None,
+            item_id: None,
+            bin: None,
+            partition_by_day: false,
+        }
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date (as midnight UTC) or an RFC 3339 timestamp.
+pub fn parse_time(value: &str) -> Result<SystemTime, AHScraperError> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().into());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(SystemTime::from)
+        .map_err(|e| AHScraperError::InvalidArgument(format!("invalid time {}: {}", value, e)))
+}
+
+pub struct ExportSummary {
+    pub rows: usize,
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum ColumnType {
+    Text,
+    Int,
+    BigInt,
+    Bool,
+    Timestamp,
+    TextArray,
+}
+
+/// Every auctions column in table order, with the type it is exported as.
+const COLUMNS: &[(&str, ColumnType)] = &[
+    ("uuid", ColumnType::Text),
+    ("auctioneer", ColumnType::Text),
+    ("profile_id", ColumnType::Text),
+    ("coop", ColumnType::TextArray),
+    ("start_time", ColumnType::Timestamp),
+    ("end_time", ColumnType::Timestamp),
+    ("item_name", ColumnType::Text),
+    ("item_uuid", ColumnType::Text),
+    ("item_lore", ColumnType::Text),
+    ("item_id", ColumnType::Text),
+    ("item_count", ColumnType::Int),
+    ("item_damage", ColumnType::Int),
+    ("enchantments", ColumnType::TextArray),
+    ("unbreakable", ColumnType::Bool),
+    ("price", ColumnType::BigInt),
+    ("claimed", ColumnType::Bool),
+    ("tier", ColumnType::Text),
+    ("category", ColumnType::Text),
+    ("last_updated", ColumnType::Timestamp),
+    ("bin", ColumnType::Bool),
+    ("reforge", ColumnType::Text),
+    ("upgrade_level", ColumnType::Int),
+    ("hot_potato_count", ColumnType::Int),
+    ("recomb", ColumnType::Bool),
+    ("unlocked_gem_slots", ColumnType::TextArray),
+    ("slotted_gems", ColumnType::TextArray),
+    ("pet_active", ColumnType::Bool),
+    ("pet_type", ColumnType::Text),
+    ("pet_held_item", ColumnType::Text),
+    ("pet_exp", ColumnType::Int),
+    ("pet_candy_used", ColumnType::Int),
+    ("dungeon_item_level", ColumnType::Int),
+    ("red_armor_coloring", ColumnType::Int),
+    ("green_armor_coloring", ColumnType::Int),
+    ("blue_armor_coloring", ColumnType::Int),
+    ("anvil_uses", ColumnType::Int),
+    ("pelts_earned", ColumnType::Int),
+    ("champion_combat_xp", ColumnType::Int),
+    ("farmed_cultivating", ColumnType::Int),
+    ("compact_blocks", ColumnType::Int),
+    ("hecatomb_s_runs", ColumnType::Int),
+    ("expertise_kills", ColumnType::Int),
+    ("runes", ColumnType::TextArray),
+    ("item_key", ColumnType::Text),
+    ("buyer", ColumnType::Text),
+    ("sold_price", ColumnType::BigInt),
+    ("highest_bid", ColumnType::BigInt),
+];
+
+/// Flattens a record into CSV cells. Arrays become JSON arrays so they survive the round trip,
+/// timestamps are milliseconds since the epoch like everywhere else we serialize them.
+fn csv_row(record: &AuctionRecord) -> Result<Vec<String>, AHScraperError> {
+    let value = serde_json::to_value(record)?;
+    Ok(COLUMNS
+        .iter()
+        .map(|(name, _)| match &value[*name] {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect())
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use super::{ColumnType, COLUMNS};
+    use crate::models::AuctionRecord;
+    use crate::AHScraperError;
+    use arrow_json::reader::Decoder;
+    use arrow_json::ReaderBuilder;
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    /// Rows buffered before being written out as a row group.
+    const BATCH_SIZE: usize = 8192;
+
+    fn schema() -> Schema {
+        Schema::new(
+            COLUMNS
+                .iter()
+                .map(|(name, column_type)| {
+                    let data_type = match column_type {
+                        ColumnType::Text => DataType::Utf8,
+                        ColumnType::Int => DataType::Int32,
+                        ColumnType::BigInt => DataType::Int64,
+                        ColumnType::Bool => DataType::Boolean,
+                        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, None),
+                        ColumnType::TextArray => {
+                            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)))
+                        }
+                    };
+                    Field::new(*name, data_type, true)
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub struct ParquetSink {
+        writer: ArrowWriter<File>,
+        decoder: Decoder,
+        buffered: usize,
+    }
+
+    impl ParquetSink {
+        pub fn new(file: File) -> Result<Self, AHScraperError> {
+            let schema = Arc::new(schema());
+            Ok(ParquetSink {
+                writer: ArrowWriter::try_new(file, schema.clone(), None)?,
+                decoder: ReaderBuilder::new(schema)
+                    .with_batch_size(BATCH_SIZE)
+                    .build_decoder()?,
+                buffered: 0,
+            })
+        }
+
+        pub fn write(&mut self, record: &AuctionRecord) -> Result<(), AHScraperError> {
+            self.decoder.serialize(std::slice::from_ref(record))?;
+            self.buffered += 1;
+            if self.buffered >= BATCH_SIZE {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), AHScraperError> {
+            if let Some(batch) = self.decoder.flush()? {
+                self.writer.write(&batch)?;
+            }
+            self.buffered = 0;
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> Result<(), AHScraperError> {
+            self.flush()?;
+            self.writer.close()?;
+            Ok(())
+        }
+    }
+}
+
+enum FileWriter {
+    Ndjson(BufWriter<File>),
+    Csv(Box<csv::Writer<File>>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet_sink::ParquetSink>),
+}
+
+impl FileWriter {
+    fn create(format: ExportFormat, path: &Path) -> Result<Self, AHScraperError> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ExportFormat::Ndjson => FileWriter::Ndjson(BufWriter::new(file)),
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer.write_record(COLUMNS.iter().map(|(name, _)| *name))?;
+                FileWriter::Csv(Box::new(writer))
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                FileWriter::Parquet(Box::new(parquet_sink::ParquetSink::new(file)?))
+            }
+        })
+    }
+
+    fn write(&mut self, record: &AuctionRecord) -> Result<(), AHScraperError> {
+        match self {
+            FileWriter::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            FileWriter::Csv(writer) => writer.write_record(csv_row(record)?)?,
+            #[cfg(feature = "parquet")]
+            FileWriter::Parquet(sink) => sink.write(record)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), AHScraperError> {
+        match self {
+            FileWriter::Ndjson(mut writer) => writer.flush()?,
+            FileWriter::Csv(mut writer) => writer.flush()?,
+            #[cfg(feature = "parquet")]
+            FileWriter::Parquet(sink) => sink.finish()?,
+        }
+        Ok(())
+    }
+}
+
+fn day_of(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%Y-%m-%d").to_string()
+}
+
+/// Streams the matching auctions out of the database into files under `options.out_dir`, only
+/// ever holding one batch of rows in memory.
+pub async fn export_auctions(
+    db: Pool<AsyncPgConnection>,
+    options: &ExportOptions,
+) -> Result<ExportSummary, AHScraperError> {
+    fs::create_dir_all(&options.out_dir)?;
+    let mut conn = db.get().await?;
+    let mut query = auctions::table.into_boxed();
+    if let Some(since) = options.since {
+        query = query.filter(auctions::start_time.ge(since));
+    }
+    if let Some(until) = options.until {
+        query = query.filter(auctions::start_time.lt(until));
+    }
+    if let Some(item_id) = &options.item_id {
+        query = query.filter(auctions::item_id.eq(item_id.clone()));
+    }
+    if let Some(bin) = options.bin {
+        query = query.filter(auctions::bin.eq(bin));
+    }
+    // Ordering by start_time means every day's rows arrive together, so only one partition file
+    // is ever open.
+    let mut rows = query
+        .order(auctions::start_time.asc())
+        .select(AuctionRecord::as_select())
+        .load_stream::<AuctionRecord>(&mut conn)
+        .await?;
+
+    let mut files = ExportFiles::new(options);
+    while let Some(record) = rows.try_next().await? {
+        files.write(&record)?;
+    }
+    files.finish()
+}
+
+/// The files an export writes to, a new one whenever the day changes when partitioned by day.
+/// Records have to come in start_time order.
+struct ExportFiles<'a> {
+    options: &'a ExportOptions,
+    current: Option<(String, FileWriter)>,
+    summary: ExportSummary,
+}
+
+impl<'a> ExportFiles<'a> {
+    fn new(options: &'a ExportOptions) -> Self {
+        ExportFiles {
+            options,
+            current: None,
+            summary: ExportSummary {
+                rows: 0,
+                files: Vec::new(),
+            },
+        }
+    }
+
+    fn write(&mut self, record: &AuctionRecord) -> Result<(), AHScraperError> {
+        let partition = if self.options.partition_by_day {
+            format!("auctions-{}", day_of(record.start_time))
+        } else {
+            "auctions".to_string()
+        };
+        if self.current.as_ref().map(|(name, _)| name) != Some(&partition) {
+            if let Some((_, writer)) = self.current.take() {
+                writer.finish()?;
+            }
+            let path = self.options.out_dir.join(format!(
+                "{}.{}",
+                partition,
+                self.options.format.extension()
+            ));
+            self.current = Some((partition, FileWriter::create(self.options.format, &path)?));
+            self.summary.files.push(path);
+        }
+        if let Some((_, writer)) = self.current.as_mut() {
+            writer.write(record)?;
+        }
+        self.summary.rows += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ExportSummary, AHScraperError> {
+        if let Some((_, writer)) = self.current {
+            writer.finish()?;
+        }
+        Ok(self.summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn record(uuid: &str, start_time: SystemTime) -> AuctionRecord {
+        AuctionRecord {
+            uuid: uuid.to_string(),
+            auctioneer: "seller".to_string(),
+            profile_id: "profile".to_string(),
+            coop: Some(vec![Some("seller".to_string()), Some("friend".to_string())]),
+            start_time,
+            end_time: start_time + Duration::from_secs(60 * 60),
+            item_name: "Hyperion".to_string(),
+            item_uuid: None,
+            item_lore: Some("Gear Score: 1,000\n\"Sharp\"".to_string()),
+            item_id: Some("HYPERION".to_string()),
+            item_count: Some(1),
+            item_damage: None,
+            enchantments: None,
+            unbreakable: None,
+            price: 100,
+            claimed: Some(false),
+            tier: "LEGENDARY".to_string(),
+            category: "weapon".to_string(),
+            last_updated: start_time,
+            bin: true,
+            reforge: None,
+            upgrade_level: None,
+            hot_potato_count: None,
+            recomb: None,
+            unlocked_gem_slots: None,
+            slotted_gems: None,
+            pet_active: None,
+            pet_type: None,
+            pet_held_item: None,
+            pet_exp: None,
+            pet_candy_used: None,
+            dungeon_item_level: None,
+            red_armor_coloring: None,
+            green_armor_coloring: None,
+            blue_armor_coloring: None,
+            anvil_uses: None,
+            pelts_earned: None,
+            champion_combat_xp: None,
+            compact_blocks: None,
+            hecatomb_s_runs: None,
+            expertise_kills: None,
+            runes: None,
+            farmed_cultivating: None,
+            item_key: Some("HYPERION".to_string()),
+            buyer: None,
+            sold_price: None,
+            highest_bid: None,
+        }
+    }
+
+    fn out_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ah-export-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn times_are_dates_or_rfc3339() {
+        assert_eq!(
+            parse_time("2024-03-01").unwrap(),
+            parse_time("2024-03-01T00:00:00Z").unwrap()
+        );
+        assert_eq!(
+            parse_time("2024-03-01T01:00:00+01:00").unwrap(),
+            parse_time("2024-03-01").unwrap()
+        );
+        assert!(parse_time("03/01/2024").is_err());
+    }
+
+    #[test]
+    fn csv_cells_survive_quotes_commas_and_newlines() {
+        let start = parse_time("2024-03-01T12:00:00Z").unwrap();
+        let row = csv_row(&record("a", start)).unwrap();
+        assert_eq!(row.len(), COLUMNS.len());
+        let cell = |name: &str| &row[COLUMNS.iter().position(|(n, _)| *n == name).unwrap()];
+        assert_eq!(cell("coop"), r#"["seller","friend"]"#);
+        assert_eq!(cell("start_time"), "1709294400000");
+        assert_eq!(cell("item_uuid"), "");
+        assert_eq!(cell("bin"), "true");
+        assert_eq!(cell("item_lore"), "Gear Score: 1,000\n\"Sharp\"");
+
+        let dir = out_dir("csv");
+        let options = ExportOptions {
+            format: ExportFormat::Csv,
+            out_dir: dir.clone(),
+            ..ExportOptions::default()
+        };
+        let mut files = ExportFiles::new(&options);
+        files.write(&record("a", start)).unwrap();
+        let summary = files.finish().unwrap();
+        let mut reader = csv::Reader::from_path(&summary.files[0]).unwrap();
+        let read: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].iter().collect::<Vec<_>>(), row);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn partitioned_exports_write_a_file_per_day() {
+        let dir = out_dir("days");
+        let options = ExportOptions {
+            out_dir: dir.clone(),
+            partition_by_day: true,
+            ..ExportOptions::default()
+        };
+        let mut files = ExportFiles::new(&options);
+        for (uuid, start) in [
+            ("a", "2024-03-01T00:00:00Z"),
+            ("b", "2024-03-01T23:59:59Z"),
+            ("c", "2024-03-02T00:00:00Z"),
+        ] {
+            files
+                .write(&record(uuid, parse_time(start).unwrap()))
+                .unwrap();
+        }
+        let summary = files.finish().unwrap();
+        assert_eq!(summary.rows, 3);
+        let names: Vec<String> = summary
+            .files
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            ["auctions-2024-03-01.ndjson", "auctions-2024-03-02.ndjson"]
+        );
+        let first = fs::read_to_string(&summary.files[0]).unwrap();
+        assert_eq!(first.lines().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
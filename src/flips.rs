@@ -0,0 +1,256 @@
+use crate::book::AuctionBook;
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::models::Auction as AuctionModel;
+use crate::schema::auctions;
+use crate::valuation;
+use crate::AHScraperError;
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, QueryableByName};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use std::cmp::Reverse;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// Thresholds a listing has to clear before it is reported as a flip.
+#[derive(Debug, Clone)]
+pub struct FlipConfig {
+    /// Minimum profit in coins after AH tax.
+    pub min_profit: i64,
+    /// Minimum profit as a fraction of the listing price.
+    pub min_margin: f64,
+    /// Minimum confidence (0.0 - 1.0) in the market value estimate.
+    pub min_confidence: f64,
+    /// Number of sold auctions at which the median is fully trusted.
+    pub trusted_sample_size: i64,
+    /// Fraction of the crafted cost of upgrades we expect to get back when selling.
+    pub modifier_weight: f64,
+}
+
+impl Default for FlipConfig {
+    fn default() -> Self {
+        FlipConfig {
+            min_profit: 500_000,
+            min_margin: 0.05,
+            min_confidence: 0.5,
+            trusted_sample_size: 10,
+            modifier_weight: 0.5,
+        }
+    }
+}
+
+/// What the market currently pays for an item key.
+#[derive(Debug, Clone, Default)]
+pub struct MarketPrice {
+    pub lowest_bin: Option<i64>,
+    pub median_sold: Option<i64>,
+    pub sold_count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlipCandidate {
+    pub uuid: String,
+    pub item_key: String,
+    pub item_name: String,
+    pub price: i64,
+    pub market_value: i64,
+    /// Crafted cost of the upgrades applied to the listed item.
+    pub modifiers_value: i64,
+    pub expected_profit: i64,
+    pub confidence: f64,
+}
+
+/// Coins lost when relisting and claiming an auction sold for `price`: the tiered listing fee
+/// plus the 1% claim tax on anything above one million.
+pub fn ah_tax(price: i64) -> i64 {
+    let listing_fee = if price < 10_000_000 {
+        price / 100
+    } else if price < 100_000_000 {
+        price * 2 / 100
+    } else {
+        price * 25 / 1000
+    };
+    let claim_tax = if price > 1_000_000 { price / 100 } else { 0 };
+    listing_fee + claim_tax
+}
+
+/// Checks a single listing against the market, returning a candidate if it clears the config.
+/// `modifiers_value` is the crafted cost of the listing's upgrades, see [`valuation`].
+pub fn evaluate(
+    auction: &AuctionModel,
+    market: &MarketPrice,
+    modifiers_value: i64,
+    config: &FlipConfig,
+) -> Option<FlipCandidate> {
+    if !auction.bin {
+        return None;
+    }
+    let item_key = auction.item_key.clone()?;
+    // How far the median can be trusted, from none at all to fully at the trusted sample size.
+    let sample_weight = market.sold_count.min(config.trusted_sample_size) as f64
+        / config.trusted_sample_size.max(1) as f64;
+    // When we have both prices we go with the lower one, flipping into a thin market is how
+    // you end up holding an item nobody wants at that price. The open floor alone is a fair
+    // estimate, sales only ever add to the confidence.
+    let (market_value, confidence) = match (market.lowest_bin, market.median_sold) {
+        (Some(lowest), Some(median)) => (lowest.min(median), 0.6 + 0.4 * sample_weight),
+        (Some(lowest), None) => (lowest, 0.6),
+        (None, Some(median)) => (median, 0.25 + 0.25 * sample_weight),
+        (None, None) => return None,
+    };
+    let market_value = market_value + (modifiers_value as f64 * config.modifier_weight) as i64;
+    let expected_profit = market_value - ah_tax(market_value) - auction.price;
+    if expected_profit < config.min_profit
+        || (expected_profit as f64) < auction.price as f64 * config.min_margin
+        || confidence < config.min_confidence
+    {
+        return None;
+    }
+    Some(FlipCandidate {
+        uuid: auction.uuid.clone(),
+        item_key,
+        item_name: auction.item_name.clone(),
+        price: auction.price,
+        market_value,
+        modifiers_value,
+        expected_profit,
+        confidence,
+    })
+}
+
+/// Lowest open BIN for the item key, optionally ignoring the listing being evaluated.
+pub async fn lowest_bin(
+    conn: &mut AsyncPgConnection,
+    key: &str,
+    exclude_uuid: Option<&str>,
+) -> Result<Option<i64>, AHScraperError> {
+    let mut query = auctions::table
+        .filter(auctions::item_key.eq(key))
+        .filter(auctions::bin.eq(true))
+        .filter(auctions::end_time.gt(SystemTime::now()))
+        .into_boxed();
+    if let Some(uuid) = exclude_uuid {
+        query = query.filter(auctions::uuid.ne(uuid));
+    }
+    Ok(query
+        .order(auctions::price.asc())
+        .select(auctions::price)
+        .first::<i64>(conn)
+        .await
+        .optional()?)
+}
+
+/// BIN sales of an item key, claimed once the ended endpoint reports them.
+#[derive(QueryableByName)]
+struct MedianSold {
+    #[diesel(sql_type = Nullable<BigInt>)]
+    median: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    sold_count: i64,
+}
+
+async fn median_sold(
+    conn: &mut AsyncPgConnection,
+    key: &str,
+) -> Result<MedianSold, AHScraperError> {
+    Ok(diesel::sql_query(
+        "SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY coalesce(sold_price, price))::bigint \
+            AS median, \
+         count(*) AS sold_count \
+         FROM auctions WHERE item_key = $1 AND bin AND claimed",
+    )
+    .bind::<Text, _>(key)
+    .get_result(conn)
+    .await?)
+}
+
+/// Evaluates newly seen auctions and returns the ones worth flipping, best first. Each is
+/// appraised against the book, only sold auctions need the database.
+pub async fn find_flips(
+    db: Pool<AsyncPgConnection>,
+    book: &RwLock<AuctionBook>,
+    bazaar: &Bazaar,
+    new_auctions: Vec<Auction>,
+    config: &FlipConfig,
+) -> Result<Vec<FlipCandidate>, AHScraperError> {
+    let mut conn = db.get().await?;
+    let mut candidates = Vec::new();
+    for auction in new_auctions.into_iter().filter(|a| a.bin) {
+        let valuation = valuation::appraise(&book.read().unwrap(), bazaar, &auction);
+        let Some(key) = valuation.item_key.as_deref() else {
+            continue;
+        };
+        let sold = median_sold(&mut conn, key).await?;
+        let market = MarketPrice {
+            lowest_bin: valuation.base_price,
+            median_sold: sold.median,
+            sold_count: sold.sold_count,
+        };
+        let auction = AuctionModel::from(auction);
+        if let Some(candidate) = evaluate(&auction, &market, valuation.modifiers_value(), config) {
+            candidates.push(candidate);
+        }
+    }
+    candidates.sort_by_key(|c| Reverse(c.expected_profit));
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_auction;
+
+    #[test]
+    fn cheap_bin_below_the_floor_is_a_flip_without_sales() {
+        let auction = test_auction("cheap", "HYPERION", 10_000_000, true);
+        let market = MarketPrice {
+            lowest_bin: Some(15_000_000),
+            median_sold: None,
+            sold_count: 0,
+        };
+        let flip = evaluate(&auction, &market, 0, &FlipConfig::default()).unwrap();
+        assert_eq!(flip.market_value, 15_000_000);
+        assert_eq!(
+            flip.expected_profit,
+            15_000_000 - ah_tax(15_000_000) - 10_000_000
+        );
+        assert!(flip.confidence >= FlipConfig::default().min_confidence);
+    }
+
+    #[test]
+    fn sales_raise_confidence_and_cap_the_value() {
+        let auction = test_auction("cheap", "HYPERION", 10_000_000, true);
+        let market = MarketPrice {
+            lowest_bin: Some(15_000_000),
+            median_sold: Some(14_000_000),
+            sold_count: 10,
+        };
+        let flip = evaluate(&auction, &market, 0, &FlipConfig::default()).unwrap();
+        assert_eq!(flip.market_value, 14_000_000);
+        assert_eq!(flip.confidence, 1.0);
+    }
+
+    #[test]
+    fn no_flip_at_the_floor_or_for_auctions() {
+        let market = MarketPrice {
+            lowest_bin: Some(10_000_000),
+            median_sold: None,
+            sold_count: 0,
+        };
+        let config = FlipConfig::default();
+        let at_floor = test_auction("floor", "HYPERION", 10_000_000, true);
+        assert!(evaluate(&at_floor, &market, 0, &config).is_none());
+        let auction = test_auction("auction", "HYPERION", 1_000_000, false);
+        assert!(evaluate(&auction, &market, 0, &config).is_none());
+        assert!(evaluate(&at_floor, &MarketPrice::default(), 0, &config).is_none());
+    }
+
+    #[test]
+    fn ah_tax_tiers() {
+        assert_eq!(ah_tax(500_000), 5_000);
+        assert_eq!(ah_tax(5_000_000), 50_000 + 50_000);
+        assert_eq!(ah_tax(50_000_000), 1_000_000 + 500_000);
+        assert_eq!(ah_tax(200_000_000), 5_000_000 + 2_000_000);
+    }
+}
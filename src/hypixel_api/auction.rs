@@ -1,15 +1,11 @@
 use crate::hypixel_api::item::ItemData;
 use base64::engine::general_purpose;
 use base64::Engine;
-use fastnbt::stream::{Parser, Value};
 use flate2::read::GzDecoder;
-use serde::{
-    de::{self, Visitor},
-    Deserialize, Deserializer, Serialize,
-};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::serde_as;
 use serde_with::TimestampMilliSeconds;
-use std::{fmt, io::Read, time::SystemTime};
+use std::{io::Read, time::SystemTime};
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -51,7 +47,7 @@ pub struct BidEntry {
     pub timestamp: SystemTime,
 }
 
-fn item_bytes_to_data<'de, D>(deserializer: D) -> Result<Option<ItemData>, D::Error>
+pub(crate) fn item_bytes_to_data<'de, D>(deserializer: D) -> Result<Option<ItemData>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -107,3 +103,31 @@ where
 struct ItemWrapper {
     pub i: Vec<ItemData>,
 }
+
+/// A listing with no item data, for tests. It started at the epoch and ends an hour later.
+#[cfg(test)]
+pub fn test_auction(uuid: &str, price: i64, bin: bool) -> Auction {
+    let start = std::time::UNIX_EPOCH;
+    Auction {
+        uuid: uuid.to_string(),
+        auctioneer: "seller".to_string(),
+        profile_id: "profile".to_string(),
+        coop: Vec::new(),
+        start,
+        end: start + std::time::Duration::from_secs(60 * 60),
+        item_name: "item".to_string(),
+        item_uuid: None,
+        item_lore: String::new(),
+        item_data: None,
+        extra: String::new(),
+        category: "misc".to_string(),
+        tier: "COMMON".to_string(),
+        starting_bid: price,
+        claimed: false,
+        claimed_bidders: Vec::new(),
+        highest_bid_amount: 0,
+        last_updated: start,
+        bin,
+        bids: Vec::new(),
+    }
+}
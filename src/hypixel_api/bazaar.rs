@@ -0,0 +1,38 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Bazaar {
+    pub success: bool,
+    pub last_updated: u64,
+    pub products: HashMap<String, Product>,
+}
+
+#[derive(Deserialize)]
+pub struct Product {
+    pub product_id: String,
+    pub quick_status: QuickStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickStatus {
+    pub sell_price: f64,
+    pub sell_volume: i64,
+    pub sell_moving_week: i64,
+    pub buy_price: f64,
+    pub buy_volume: i64,
+    pub buy_moving_week: i64,
+}
+
+impl Bazaar {
+    /// What it costs to instantly buy one of `product_id`, rounded to whole coins.
+    pub fn buy_price(&self, product_id: &str) -> Option<i64> {
+        self.products
+            .get(product_id)
+            .map(|p| p.quick_status.buy_price)
+            .filter(|price| *price > 0.0)
+            .map(|price| price.round() as i64)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::hypixel_api::auction::item_bytes_to_data;
+use crate::hypixel_api::item::ItemData;
+use serde::Deserialize;
+use serde_with::serde_as;
+use serde_with::TimestampMilliSeconds;
+use std::time::SystemTime;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EndedAuctions {
+    pub success: bool,
+    pub last_updated: u64,
+    pub auctions: Vec<EndedAuction>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone)]
+pub struct EndedAuction {
+    pub auction_id: String,
+    pub seller: String,
+    pub seller_profile: String,
+    pub buyer: String,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub timestamp: SystemTime,
+    pub price: i64,
+    pub bin: bool,
+    #[serde(rename = "item_bytes", deserialize_with = "item_bytes_to_data")]
+    pub item_data: Option<ItemData>,
+}
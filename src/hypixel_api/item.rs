@@ -71,6 +71,8 @@ pub struct ExtraAttributes {
     pub hecatomb_s_runs: Option<u32>,
     pub expertise_kills: Option<u32>,
     pub farming_for_dummies_count: Option<u32>,
+    pub art_of_war_count: Option<u32>,
+    pub ability_scroll: Option<Vec<String>>,
     pub runes: Option<HashMap<String, u8>>,
     #[serde(default)]
     #[serde(rename = "petInfo", deserialize_with = "pet_json_to_struct")]
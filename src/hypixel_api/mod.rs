@@ -1,39 +1,137 @@
+use crate::archive::SnapshotArchiver;
 use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::hypixel_api::ended::EndedAuctions;
 use crate::hypixel_api::page::Page;
-use std::error::Error;
-use std::thread::sleep;
-use std::time::Instant;
+use crate::hypixel_api::source::PageSource;
+use crate::AHScraperError;
 
 pub mod auction;
+pub mod bazaar;
+pub mod ended;
 pub mod item;
 pub mod page;
+pub mod source;
 
-pub async fn get_page(page: u32) -> Result<Page, reqwest::Error> {
-    //let start = Instant::now();
-    let response = reqwest::get(format!(
-        "https://api.hypixel.net/v2/skyblock/auctions?page={}",
-        page
-    ))
-    .await?;
-    //println!("Time for request: {:?}", start.elapsed());
-    //let start = Instant::now();
-    response.json::<Page>().await
-    //println!("Time for json: {:?}", start.elapsed());
+pub const DEFAULT_BASE_URL: &str = "https://api.hypixel.net/v2";
+
+/// Client for the SkyBlock endpoints of the Hypixel API, sending the API key with every request
+/// when one is set.
+#[derive(Clone)]
+pub struct HypixelClient {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Default for HypixelClient {
+    fn default() -> Self {
+        HypixelClient::new(DEFAULT_BASE_URL, None)
+    }
+}
+
+impl HypixelClient {
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        HypixelClient {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(format!("{}/{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.header("API-Key", key),
+            None => request,
+        }
+    }
+
+    /// Fetches a page of auctions, handing the raw response to `archiver` if one is given.
+    pub async fn get_page(
+        &self,
+        page: u32,
+        archiver: Option<&SnapshotArchiver>,
+    ) -> Result<Page, AHScraperError> {
+        //let start = Instant::now();
+        let response = self
+            .get(&format!("skyblock/auctions?page={}", page))
+            .send()
+            .await?;
+        let body = response.bytes().await?;
+        //println!("Time for request: {:?}", start.elapsed());
+        //let start = Instant::now();
+        let parsed = serde_json::from_slice::<Page>(&body)?;
+        //println!("Time for json: {:?}", start.elapsed());
+        if let Some(archiver) = archiver {
+            let archiver = archiver.clone();
+            let last_updated = parsed.last_updated;
+            // compressing a page takes a few ms, keep it off the runtime and out of the scrape loop.
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = archiver.store(last_updated, page, &body) {
+                    println!("Failed to archive page {} of {}: {}", page, last_updated, e);
+                }
+            });
+        }
+        Ok(parsed)
+    }
+
+    pub async fn get_bazaar(&self) -> Result<Bazaar, reqwest::Error> {
+        self.get("skyblock/bazaar")
+            .send()
+            .await?
+            .json::<Bazaar>()
+            .await
+    }
+
+    /// Auctions that ended (sold or were cancelled with bids) in the last 60 seconds.
+    pub async fn get_ended_auctions(&self) -> Result<EndedAuctions, reqwest::Error> {
+        self.get("skyblock/auctions_ended")
+            .send()
+            .await?
+            .json::<EndedAuctions>()
+            .await
+    }
+}
+
+pub async fn get_all_auctions(source: &dyn PageSource) -> Result<Vec<Auction>, AHScraperError> {
+    Ok(get_full_scan(source).await?.auctions)
 }
 
-pub async fn get_all_auctions() -> Result<Vec<Auction>, reqwest::Error> {
-    let first_page = get_page(0).await?;
+/// Every page of the auction house.
+pub struct FullScan {
+    pub auctions: Vec<Auction>,
+    /// Every page came from the same refresh as page 0 and none came back empty, so an auction
+    /// missing from `auctions` really isn't listed anymore.
+    pub complete: bool,
+}
+
+pub async fn get_full_scan(source: &dyn PageSource) -> Result<FullScan, AHScraperError> {
+    let first_page = source.page(0).await?;
+    let mut complete = !first_page.auctions.is_empty();
     let mut v = first_page.auctions;
     for i in 1..first_page.total_pages {
-        v.append(&mut get_page(i).await?.auctions);
+        let mut page = source.page_in(i, first_page.last_updated).await?;
+        complete &= page.last_updated == first_page.last_updated && !page.auctions.is_empty();
+        v.append(&mut page.auctions);
     }
-    Ok(v)
+    Ok(FullScan {
+        auctions: v,
+        complete,
+    })
 }
 
-pub async fn get_first_x_pages_of_auctions(x: u32) -> Result<Vec<Auction>, reqwest::Error> {
-    let mut v = vec![];
-    for i in 0..x {
-        v.append(&mut get_page(i).await?.auctions);
+pub async fn get_first_x_pages_of_auctions(
+    x: u32,
+    source: &dyn PageSource,
+) -> Result<Vec<Auction>, AHScraperError> {
+    if x == 0 {
+        return Ok(Vec::new());
+    }
+    let first_page = source.page(0).await?;
+    let mut v = first_page.auctions;
+    for i in 1..x {
+        v.append(&mut source.page_in(i, first_page.last_updated).await?.auctions);
     }
     Ok(v)
 }
@@ -1,6 +1,5 @@
 use crate::hypixel_api::auction::Auction;
-use serde::{Deserialize, Serialize};
-use std::error::Error;
+use serde::Deserialize;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -0,0 +1,65 @@
+use crate::archive::SnapshotArchiver;
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::hypixel_api::ended::EndedAuctions;
+use crate::hypixel_api::page::Page;
+use crate::hypixel_api::HypixelClient;
+use crate::AHScraperError;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::time::SystemTime;
+
+/// Where the scraper gets its Hypixel data from, so the same pipeline can run against the live
+/// API or recorded snapshots.
+pub trait PageSource: Send + Sync {
+    fn page(&self, page: u32) -> BoxFuture<'_, Result<Page, AHScraperError>>;
+
+    /// A later page of the refresh page 0 came from, so a scan reads one refresh throughout.
+    /// The live API only serves its latest refresh.
+    fn page_in(
+        &self,
+        page: u32,
+        _last_updated: u64,
+    ) -> BoxFuture<'_, Result<Page, AHScraperError>> {
+        self.page(page)
+    }
+
+    fn bazaar(&self) -> BoxFuture<'_, Result<Bazaar, AHScraperError>>;
+
+    fn ended_auctions(&self) -> BoxFuture<'_, Result<EndedAuctions, AHScraperError>>;
+
+    /// The time as far as the data is concerned, used to tell which auctions have ended.
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Whether the source has nothing left to give, the live API never runs out.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// The Hypixel API, optionally archiving every auctions page it fetches.
+pub struct LivePageSource {
+    client: HypixelClient,
+    archiver: Option<SnapshotArchiver>,
+}
+
+impl LivePageSource {
+    pub fn new(client: HypixelClient, archiver: Option<SnapshotArchiver>) -> Self {
+        LivePageSource { client, archiver }
+    }
+}
+
+impl PageSource for LivePageSource {
+    fn page(&self, page: u32) -> BoxFuture<'_, Result<Page, AHScraperError>> {
+        self.client.get_page(page, self.archiver.as_ref()).boxed()
+    }
+
+    fn bazaar(&self) -> BoxFuture<'_, Result<Bazaar, AHScraperError>> {
+        async { Ok(self.client.get_bazaar().await?) }.boxed()
+    }
+
+    fn ended_auctions(&self) -> BoxFuture<'_, Result<EndedAuctions, AHScraperError>> {
+        async { Ok(self.client.get_ended_auctions().await?) }.boxed()
+    }
+}
@@ -0,0 +1,72 @@
+use crate::AHScraperError;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use std::time::SystemTime;
+
+// item_lineage is a view so diesel print-schema won't pick it up, see the migration for how it
+// decides what sold.
+diesel::table! {
+    item_lineage (auction_uuid) {
+        auction_uuid -> Text,
+        item_uuid -> Text,
+        item_key -> Nullable<Text>,
+        item_name -> Text,
+        auctioneer -> Text,
+        profile_id -> Text,
+        price -> Int8,
+        bin -> Bool,
+        start_time -> Timestamp,
+        end_time -> Timestamp,
+        sold_price -> Nullable<Int8>,
+        next_auctioneer -> Nullable<Text>,
+        listing_number -> Int8,
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = item_lineage)]
+pub struct LineageEntry {
+    pub auction_uuid: String,
+    pub item_uuid: String,
+    pub item_key: Option<String>,
+    pub item_name: String,
+    pub auctioneer: String,
+    pub profile_id: String,
+    pub price: i64,
+    pub bin: bool,
+    pub start_time: SystemTime,
+    pub end_time: SystemTime,
+    pub sold_price: Option<i64>,
+    pub next_auctioneer: Option<String>,
+    pub listing_number: i64,
+}
+
+/// Every auction an item has been listed in, oldest first.
+pub async fn history_for_item_uuid(
+    conn: &mut AsyncPgConnection,
+    item_uuid: &str,
+) -> Result<Vec<LineageEntry>, AHScraperError> {
+    Ok(item_lineage::table
+        .filter(item_lineage::item_uuid.eq(item_uuid))
+        .order(item_lineage::start_time.asc())
+        .select(LineageEntry::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// The prices an item actually sold for over its lifetime, oldest first.
+pub async fn realized_prices(
+    conn: &mut AsyncPgConnection,
+    item_uuid: &str,
+) -> Result<Vec<(SystemTime, i64)>, AHScraperError> {
+    Ok(item_lineage::table
+        .filter(item_lineage::item_uuid.eq(item_uuid))
+        .filter(item_lineage::sold_price.is_not_null())
+        .order(item_lineage::start_time.asc())
+        .select((
+            item_lineage::start_time,
+            item_lineage::sold_price.assume_not_null(),
+        ))
+        .load(conn)
+        .await?)
+}
@@ -1,117 +1,860 @@
+use crate::analysis::AnalysisConfig;
+use crate::archive::{RetentionPolicy, SnapshotArchiver};
+use crate::book::{AuctionBook, SharedBook};
+use crate::changes::ChangeIndex;
+use crate::cli::{BenchIngestArgs, Cli, Command, QueryCommand, ReplayArgs, ReprocessArgs};
+use crate::config::{Config, IngestMode};
+use crate::export::ExportOptions;
 use crate::hypixel_api::auction::Auction;
-use crate::hypixel_api::{get_all_auctions, get_first_x_pages_of_auctions};
-use diesel::upsert::excluded;
-use diesel::ExpressionMethods;
-use models::Auction as AuctionModel;
-use schema::auctions;
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::hypixel_api::page::Page;
+use crate::hypixel_api::source::{LivePageSource, PageSource};
+use crate::hypixel_api::{
+    get_all_auctions, get_first_x_pages_of_auctions, get_full_scan, FullScan,
+};
+use crate::replay::ReplayPageSource;
+use clap::Parser;
+use events::{AuctionEvent, AuctionTracker, EventKind, EventSender};
+use flips::{FlipCandidate, FlipConfig};
+use models::{Auction as AuctionModel, NewSavedSearch};
+use partitions::{ExpiredPartitions, MaintenanceReport};
+use shutdown::Shutdown;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
-use std::{env, fmt};
-use tokio::task;
+use std::{env, fmt, io};
+use store::postgres::PostgresStore;
+#[cfg(feature = "sqlite")]
+use store::sqlite::SqliteStore;
+use store::AuctionStore;
+use supervisor::Supervisor;
+use tokio::sync::broadcast;
 use tokio::task::JoinError;
-use tokio::time;
 use tokio::time::Instant;
+use upsert::RefreshColumns;
+use webhooks::WebhookDispatcher;
+pub mod analysis;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod archive;
+pub mod book;
+pub mod bulk;
+pub mod changes;
+pub mod cli;
+pub mod config;
+pub mod discord;
+pub mod events;
+pub mod export;
+pub mod flips;
 pub mod hypixel_api;
+pub mod lineage;
+pub mod migrations;
 pub mod models;
+pub mod partitions;
+pub mod queries;
+pub mod replay;
+pub mod saved_searches;
 pub mod schema;
+pub mod sellers;
+pub mod shutdown;
+pub mod store;
+pub mod supervisor;
+pub mod upsert;
+pub mod valuation;
+pub mod webhooks;
 use diesel_async::{
-    pooled_connection::{bb8::Pool, AsyncDieselConnectionManager},
-    AsyncPgConnection, RunQueryDsl,
+    pooled_connection::{bb8::Pool, AsyncDieselConnectionManager, ManagerConfig},
+    AsyncPgConnection,
 };
 
 #[tokio::main]
 async fn main() -> Result<(), AHScraperError> {
     env_logger::init();
     dotenvy::from_filename(".env.local").or(dotenvy::dotenv())?;
-    println!("Starting AH Scraper!");
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or_default();
+    let config = match load_config(cli.config.as_deref(), cli.database_url, &command) {
+        Ok(config) => config,
+        Err(e) => {
+            // printed with Display so every problem shows up on its own line.
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match command {
+        Command::Scrape(_) => {
+            println!("Starting AH Scraper!");
+            let archiver = config.archive.archiver();
+            if let Some(archiver) = &archiver {
+                println!(
+                    "Archiving raw auction pages to {}",
+                    archiver.dir().display()
+                );
+            }
+            let source = LivePageSource::new(config.hypixel_client(), archiver);
+            run(Arc::new(source), config).await
+        }
+        Command::SyncOnce(_) => sync_once_command(&config).await,
+        Command::Migrate => migrate_command(&config).await,
+        Command::Export(args) => export_command(&config, args.into()).await,
+        Command::Replay(args) => replay_command(config, args).await,
+        Command::Query(query) => query_command(&config, query).await,
+        Command::Reprocess(args) => reprocess_command(&config, args).await,
+        Command::BenchIngest(args) => bench_ingest_command(config, args).await,
+        Command::MaintainPartitions => maintain_partitions_command(&config).await,
+    }
+}
+
+fn load_config(
+    path: Option<&Path>,
+    database_url: Option<String>,
+    command: &Command,
+) -> Result<Config, AHScraperError> {
+    let mut config = Config::load(path)?;
+    if let Some(database_url) = database_url {
+        config.database.url = Some(database_url);
+    }
+    command.apply(&mut config);
+    config.validate()?;
+    Ok(config)
+}
+
+async fn connect(config: &Config) -> Result<Pool<AsyncPgConnection>, AHScraperError> {
+    let mut manager_config = ManagerConfig::default();
+    manager_config.custom_setup = Box::new(store::postgres::establish);
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(
+        config.database_url(),
+        manager_config,
+    );
+    // copy ingest holds one more connection of its own, outside the pool.
+    let reserved = u32::from(config.scrape.ingest == IngestMode::Copy);
+    Ok(Pool::builder()
+        .max_size(config.database.pool_size - reserved)
+        .build(manager)
+        .await?)
+}
+
+/// Connects for a command that writes auctions, migrating first unless that is turned off and
+/// refusing to go on if the tables don't match the models.
+async fn connect_for_writes(config: &Config) -> Result<Pool<AsyncPgConnection>, AHScraperError> {
+    if config.database.auto_migrate {
+        for version in migrations::run_pending(config.database_url()).await? {
+            println!("Applied migration {}", version);
+        }
+    }
+    let pool = connect(config).await?;
+    migrations::verify_schema(&mut *pool.get().await?).await?;
+    Ok(pool)
+}
+
+/// Opens the auction store `database.url` points at, a SQLite file with the sqlite feature and
+/// Postgres otherwise, which also hands back its pool for the jobs that only run on Postgres.
+/// `writes` migrates and checks the schema first, like [`connect_for_writes`].
+async fn open_store(
+    config: &Config,
+    writes: bool,
+) -> Result<(Arc<dyn AuctionStore>, Option<Pool<AsyncPgConnection>>), AHScraperError> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = store::sqlite_path(config.database_url()) {
+        return Ok((Arc::new(SqliteStore::open(path).await?), None));
+    }
+    let pool = if writes {
+        connect_for_writes(config).await?
+    } else {
+        connect(config).await?
+    };
+    Ok((
+        Arc::new(PostgresStore::new(pool.clone(), config)),
+        Some(pool),
+    ))
+}
+
+/// Indexes every auction from `source` then keeps scraping it until it runs out.
+async fn run(source: Arc<dyn PageSource>, config: Config) -> Result<(), AHScraperError> {
+    let shutdown = Shutdown::listen();
+    let supervisor = Supervisor::default();
+    let started = Instant::now();
+    let (store, pool) = open_store(&config, true).await?;
+    println!("Finished Connecting to db");
+    if pool.is_none() {
+        println!("Flips, alerts, analytics and the query API need Postgres, running without them");
+    }
+    let work = index_and_scrape(store, pool, source, &config, &shutdown, &supervisor);
+    let result = shutdown.drain(work, config.scrape.drain_timeout()).await;
+    shutdown.print_summary(started, result.is_some());
+    supervisor.print_summary();
+    result.unwrap_or(Ok(()))
+}
+
+async fn index_and_scrape(
+    store: Arc<dyn AuctionStore>,
+    pool: Option<Pool<AsyncPgConnection>>,
+    source: Arc<dyn PageSource>,
+    config: &Config,
+    shutdown: &Shutdown,
+    supervisor: &Supervisor,
+) -> Result<(), AHScraperError> {
     println!("Indexing all auctions on the AH to the database.");
     let start = Instant::now();
-    let auctions_list = hypixel_api::get_all_auctions().await?;
+    // nothing is written while fetching, so it can stop right away.
+    let auctions_list = tokio::select! {
+        auctions = supervisor.retry("initial fetch", shutdown, || get_all_auctions(source.as_ref())) => auctions,
+        _ = shutdown.cancelled() => None,
+    };
+    let Some(auctions_list) = auctions_list else {
+        return Ok(());
+    };
     println!("Finished scraping all auctions in: {:.2?}", start.elapsed());
-    let config =
-        AsyncDieselConnectionManager::<AsyncPgConnection>::new(std::env::var("DATABASE_URL")?);
-    let pool = Pool::builder().build(config).await?;
-    println!("Finished Connecting to db, upserting all data now");
+    if let Some(pool) = pool.as_ref().filter(|_| !config.alerts.rules.is_empty()) {
+        let rules: Vec<NewSavedSearch> = config.alerts.rules.iter().map(Into::into).collect();
+        let sync = async { saved_searches::sync_rules(&mut *pool.get().await?, &rules).await };
+        match supervisor.run_once("alert rule sync", sync).await {
+            Ok(created) => println!(
+                "Synced {} alert rules ({} new) from the config",
+                rules.len(),
+                created
+            ),
+            Err(e) => println!("Alert rule sync failed: {}", e),
+        }
+    }
+    let start = Instant::now();
+    let indexed = supervisor
+        .retry("initial index", shutdown, || {
+            store.upsert(&auctions_list, &config.scrape.refresh_columns)
+        })
+        .await;
+    if indexed.is_none() {
+        return Ok(());
+    }
+    let mut tracker = AuctionTracker::default();
+    tracker.observe(&auctions_list);
+    let mut written = ChangeIndex::default();
+    written.record(&auctions_list);
+    shutdown.stats().record_written(auctions_list.len());
+    let mut book = AuctionBook::default();
+    book.extend(auctions_list.into_iter().map(AuctionModel::from));
+    let book: SharedBook = Arc::new(RwLock::new(book));
+    println!("Finished indexing all auctions in: {:.2?}", start.elapsed());
+    if let Some(pool) = pool.as_ref().filter(|_| config.jobs.analytics) {
+        shutdown.spawn(analysis_task(
+            pool.clone(),
+            AnalysisConfig::default(),
+            Duration::from_secs(config.jobs.analysis_interval_secs),
+            shutdown.clone(),
+            supervisor.clone(),
+        ));
+        shutdown.spawn(sellers_task(
+            pool.clone(),
+            Duration::from_secs(config.jobs.sellers_interval_secs),
+            shutdown.clone(),
+            supervisor.clone(),
+        ));
+    }
+    if let Some(pool) = pool.as_ref().filter(|_| config.jobs.partitions) {
+        shutdown.spawn(partitions_task(
+            pool.clone(),
+            config.database.retention_months,
+            config.database.expired_partitions,
+            Duration::from_secs(config.jobs.partitions_interval_secs),
+            shutdown.clone(),
+            supervisor.clone(),
+        ));
+    }
+    let (events, _) = broadcast::channel(events::EVENT_BUFFER);
+    #[cfg(feature = "api")]
+    if let Some(db) = pool.clone() {
+        let addr = config.server.addr.clone();
+        let book = book.clone();
+        let events = events.clone();
+        let supervisor = supervisor.clone();
+        let api_shutdown = shutdown.clone();
+        shutdown.spawn(async move {
+            if let Err(e) = api::serve(db, book, events, supervisor, api_shutdown, &addr).await {
+                println!("Query API stopped: {}", e);
+            }
+        });
+    }
+    let scraper = Scraper {
+        store,
+        db: pool,
+        source,
+        config,
+        flip_config: config.alerts.flip_config(),
+        tracker: Mutex::new(tracker),
+        written: Mutex::new(written),
+        book,
+        bazaar: Mutex::new(Arc::default()),
+        ended_last_updated: AtomicU64::new(0),
+        events,
+        dispatcher: WebhookDispatcher::default().with_shutdown(shutdown.clone()),
+        shutdown,
+        supervisor,
+    };
+    scraper.run().await;
+    Ok(())
+}
+
+async fn sync_once_command(config: &Config) -> Result<(), AHScraperError> {
+    let (store, _) = open_store(config, true).await?;
+    let start = Instant::now();
+    let source = LivePageSource::new(config.hypixel_client(), config.archive.archiver());
+    let auctions = get_all_auctions(&source).await?;
+    println!(
+        "Finished scraping {} auctions in: {:.2?}",
+        auctions.len(),
+        start.elapsed()
+    );
     let start = Instant::now();
-    process_auctions_in_parallel(pool.clone(), auctions_list, 100).await?;
+    store
+        .upsert(&auctions, &config.scrape.refresh_columns)
+        .await?;
     println!("Finished indexing all auctions in: {:.2?}", start.elapsed());
-    scrape_task(pool).await?;
     Ok(())
 }
 
-async fn scrape_task(db: Pool<AsyncPgConnection>) -> Result<(), AHScraperError> {
-    let mut interval = time::interval(Duration::from_millis(100));
-    let mut counter = 0;
-    loop {
-        interval.tick().await;
-        // every 10 minutes we should scan all pages as to update on auctions that may have moved.
-        if counter > 12000 {
-            let db = db.clone();
-            tokio::spawn(async move {
-                if let Err(e) =
-                    process_auctions_in_parallel(db, get_all_auctions().await.unwrap(), 100).await
-                {
-                    println!("Database operation failed: {}", e);
+async fn migrate_command(config: &Config) -> Result<(), AHScraperError> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = store::sqlite_path(config.database_url()) {
+        // the SQLite tables are created whenever the store is opened.
+        SqliteStore::open(path).await?;
+        println!("Database is up to date");
+        return Ok(());
+    }
+    let applied = migrations::run_pending(config.database_url()).await?;
+    if applied.is_empty() {
+        println!("Database is up to date");
+    }
+    for version in applied {
+        println!("Applied migration {}", version);
+    }
+    Ok(())
+}
+
+async fn export_command(config: &Config, options: ExportOptions) -> Result<(), AHScraperError> {
+    if store::sqlite_path(config.database_url()).is_some() {
+        return Err(AHScraperError::InvalidArgument(
+            "export needs a Postgres database".to_string(),
+        ));
+    }
+    let pool = connect(config).await?;
+    let start = Instant::now();
+    let summary = export::export_auctions(pool, &options).await?;
+    println!(
+        "Exported {} auctions to {} files in: {:.2?}",
+        summary.rows,
+        summary.files.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+async fn replay_command(config: Config, args: ReplayArgs) -> Result<(), AHScraperError> {
+    let source = ReplayPageSource::new(args.dir, args.speed)?;
+    println!("Replaying {} archived snapshots", source.snapshot_count());
+    let start = Instant::now();
+    run(Arc::new(source), config).await?;
+    println!("Finished replay in: {:.2?}", start.elapsed());
+    Ok(())
+}
+
+async fn query_command(config: &Config, query: QueryCommand) -> Result<(), AHScraperError> {
+    let (store, pool) = open_store(config, false).await?;
+    // everything past the store's own lookups uses the indexes only Postgres has.
+    let postgres = || {
+        pool.as_ref().ok_or_else(|| {
+            AHScraperError::InvalidArgument("this query needs a Postgres database".to_string())
+        })
+    };
+    let auctions = match query {
+        QueryCommand::LowestBin { item_key } => {
+            match store.lowest_bin(&item_key).await? {
+                Some(price) => println!("Lowest BIN for {}: {}", item_key, price),
+                None => println!("No open BIN listings for {}", item_key),
+            }
+            return Ok(());
+        }
+        QueryCommand::Auctions { item_key, limit } => store.by_item_key(&item_key, limit).await?,
+        QueryCommand::ItemId { item_id, limit } => {
+            queries::by_item_id(&mut *postgres()?.get().await?, &item_id, limit).await?
+        }
+        QueryCommand::Seller { auctioneer, limit } => {
+            queries::by_seller(&mut *postgres()?.get().await?, &auctioneer, limit).await?
+        }
+        QueryCommand::History { item_uuid } => {
+            queries::item_history(&mut *postgres()?.get().await?, &item_uuid).await?
+        }
+        QueryCommand::EndingSoon { minutes, limit } => {
+            let within = Duration::from_secs(minutes * 60);
+            queries::ending_soon(&mut *postgres()?.get().await?, within, limit).await?
+        }
+        QueryCommand::Enchantment {
+            enchantment,
+            min_level,
+            limit,
+        } => {
+            let conn = &mut *postgres()?.get().await?;
+            queries::with_enchantment(conn, &enchantment, min_level, limit).await?
+        }
+        QueryCommand::Rune {
+            rune,
+            min_level,
+            limit,
+        } => queries::with_rune(&mut *postgres()?.get().await?, &rune, min_level, limit).await?,
+    };
+    for auction in auctions {
+        println!(
+            "{} {} {} by {} ({})",
+            auction.price,
+            if auction.bin { "BIN" } else { "auction" },
+            auction.item_name,
+            auction.auctioneer,
+            auction.uuid
+        );
+    }
+    Ok(())
+}
+
+/// Runs every archived page through the parser again, so parser fixes reach stored auctions.
+async fn reprocess_command(config: &Config, args: ReprocessArgs) -> Result<(), AHScraperError> {
+    let dir = args.archive.or(config.archive.dir.clone()).ok_or_else(|| {
+        AHScraperError::InvalidArgument("reprocess needs --archive or archive.dir".to_string())
+    })?;
+    let archive = SnapshotArchiver::new(dir, RetentionPolicy::default());
+    let snapshots = archive.snapshots()?;
+    let (store, _) = open_store(config, true).await?;
+    let start = Instant::now();
+    let mut total = 0;
+    for (i, last_updated) in snapshots.iter().enumerate() {
+        let auctions = read_snapshot(&archive, *last_updated)?;
+        total += auctions.len();
+        // reprocessing is for getting parser fixes into stored auctions, so everything is rewritten.
+        store.upsert(&auctions, &RefreshColumns::full()).await?;
+        println!(
+            "Reprocessed snapshot {} ({}/{})",
+            last_updated,
+            i + 1,
+            snapshots.len()
+        );
+    }
+    println!(
+        "Reprocessed {} auctions from {} snapshots in: {:.2?}",
+        total,
+        snapshots.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// Every auction in one archived snapshot.
+fn read_snapshot(
+    archive: &SnapshotArchiver,
+    last_updated: u64,
+) -> Result<Vec<Auction>, AHScraperError> {
+    let mut auctions = Vec::new();
+    for page in 0.. {
+        let path = archive.page_path(last_updated, page);
+        if !path.exists() {
+            break;
+        }
+        let body = archive::read_page(&path)?;
+        auctions.append(&mut serde_json::from_slice::<Page>(&body)?.auctions);
+    }
+    Ok(auctions)
+}
+
+/// Writes the same auctions with each ingest mode and reports how long every round took.
+async fn bench_ingest_command(
+    mut config: Config,
+    args: BenchIngestArgs,
+) -> Result<(), AHScraperError> {
+    // copy rounds need their connection on top of the pool.
+    config.scrape.ingest = IngestMode::Copy;
+    config.validate()?;
+    let auctions = match args.archive.or(config.archive.dir.clone()) {
+        Some(dir) => {
+            let archive = SnapshotArchiver::new(dir, RetentionPolicy::default());
+            let newest = archive.snapshots()?.into_iter().max().ok_or_else(|| {
+                AHScraperError::InvalidArgument(format!(
+                    "no snapshots in {}",
+                    archive.dir().display()
+                ))
+            })?;
+            read_snapshot(&archive, newest)?
+        }
+        None => get_all_auctions(&LivePageSource::new(config.hypixel_client(), None)).await?,
+    };
+    let store = PostgresStore::new(connect_for_writes(&config).await?, &config);
+    println!(
+        "Writing {} auctions {} times per mode",
+        auctions.len(),
+        args.rounds
+    );
+    for ingest in [IngestMode::Chunked, IngestMode::Copy] {
+        let store = store.clone().with_ingest(ingest);
+        let mut total = Duration::ZERO;
+        for round in 1..=args.rounds {
+            let start = Instant::now();
+            store
+                .upsert(&auctions, &config.scrape.refresh_columns)
+                .await?;
+            println!("  {:?} round {}: {:.2?}", ingest, round, start.elapsed());
+            total += start.elapsed();
+        }
+        println!(
+            "{:?}: {:.2?} on average",
+            ingest,
+            total / args.rounds.max(1)
+        );
+    }
+    Ok(())
+}
+
+/// What the scrape jobs share, each job runs on its own schedule under the supervisor.
+struct Scraper<'a> {
+    store: Arc<dyn AuctionStore>,
+    /// Only on Postgres, for alerts and flips.
+    db: Option<Pool<AsyncPgConnection>>,
+    source: Arc<dyn PageSource>,
+    config: &'a Config,
+    flip_config: FlipConfig,
+    tracker: Mutex<AuctionTracker>,
+    /// What the database already has, so quick scans only write what changed.
+    written: Mutex<ChangeIndex>,
+    /// Every live auction, for the lookups alerts and flips make.
+    book: SharedBook,
+    bazaar: Mutex<Arc<Bazaar>>,
+    ended_last_updated: AtomicU64,
+    events: EventSender,
+    dispatcher: WebhookDispatcher,
+    shutdown: &'a Shutdown,
+    supervisor: &'a Supervisor,
+}
+
+impl Scraper<'_> {
+    /// Runs the jobs until shutdown or until the source runs out. A job that already started
+    /// finishes its writes before shutdown is noticed.
+    async fn run(&self) {
+        let scan = &self.config.scrape;
+        let jobs = &self.config.jobs;
+        tokio::join!(
+            self.supervisor
+                .run_periodic("quick scan", scan.tick_interval(), self.shutdown, || {
+                    self.quick_scan()
+                }),
+            self.supervisor.run_scheduled(
+                "full scan",
+                scan.full_scan_interval(),
+                self.shutdown,
+                || self.full_scan()
+            ),
+            async {
+                // the bazaar only updates every few seconds, no point hitting it each tick.
+                if jobs.bazaar {
+                    self.supervisor
+                        .run_periodic("bazaar", scan.bazaar_interval(), self.shutdown, || {
+                            self.refresh_bazaar()
+                        })
+                        .await
                 }
-            });
-        } else {
-            process_auctions_in_parallel(db.clone(), get_first_x_pages_of_auctions(10).await?, 100)
+            },
+            async {
+                if jobs.ended_auctions {
+                    self.supervisor
+                        .run_periodic(
+                            "ended auctions",
+                            scan.ended_interval(),
+                            self.shutdown,
+                            || self.ended_auctions(),
+                        )
+                        .await
+                }
+            },
+        );
+    }
+
+    async fn quick_scan(&self) -> Result<(), AHScraperError> {
+        let scan = &self.config.scrape;
+        let auctions = get_first_x_pages_of_auctions(scan.pages, self.source.as_ref()).await?;
+        // the tracker only takes the batch in once it's written and alerted on, so a scan that
+        // fails part way sees the same auctions as new again next time.
+        let new_auctions: Vec<Auction> = {
+            let tracker = self.tracker.lock().unwrap();
+            auctions
+                .iter()
+                .filter(|a| !tracker.is_tracked(&a.uuid))
+                .cloned()
+                .collect()
+        };
+        let (changed, counts) = self.written.lock().unwrap().diff(&auctions);
+        self.store
+            .upsert(&changed, &self.config.scrape.refresh_columns)
+            .await?;
+        self.written.lock().unwrap().record(&changed);
+        self.shutdown.stats().record_written(changed.len());
+        self.shutdown.stats().record_unchanged(counts.unchanged);
+        self.book
+            .write()
+            .unwrap()
+            .extend(changed.into_iter().map(AuctionModel::from));
+        let mut profits = HashMap::new();
+        if let Some(db) = &self.db {
+            let new_models: Vec<AuctionModel> = new_auctions
+                .iter()
+                .cloned()
+                .map(AuctionModel::from)
+                .collect();
+            saved_searches::notify_matches(db.clone(), &self.book, &self.dispatcher, &new_models)
                 .await?;
+            let bazaar = self.bazaar.lock().unwrap().clone();
+            profits = report_flips(
+                db.clone(),
+                &self.book,
+                &bazaar,
+                new_auctions,
+                &self.flip_config,
+            )
+            .await?
+            .into_iter()
+            .map(|flip| (flip.uuid, flip.expected_profit))
+            .collect();
+        }
+        let mut changes = self.tracker.lock().unwrap().observe(&auctions);
+        for event in changes.iter_mut() {
+            event.expected_profit = profits.get(&event.uuid).copied();
+        }
+        let now = self.source.now();
+        changes.extend(self.tracker.lock().unwrap().expire(now));
+        self.book.write().unwrap().expire(now);
+        self.written.lock().unwrap().prune(now);
+        self.send(changes);
+        self.shutdown.stats().record_quick_scan();
+        println!(
+            "Finished quick scan update: {} new, {} updated, {} unchanged",
+            counts.new, counts.updated, counts.unchanged
+        );
+        if self.source.finished() {
+            self.shutdown.stop();
+        }
+        Ok(())
+    }
+
+    /// Scans every page to catch what quick scans missed: bids on auctions past the first pages
+    /// and auctions that were bought or cancelled.
+    async fn full_scan(&self) -> Result<(), AHScraperError> {
+        let start = Instant::now();
+        let scan_started = self.source.now();
+        let FullScan { auctions, complete } = get_full_scan(self.source.as_ref()).await?;
+        let mut changes = self.tracker.lock().unwrap().observe(&auctions);
+        let added = changes
+            .iter()
+            .filter(|e| matches!(e.kind, EventKind::NewAuction))
+            .count();
+        let changed = changes.len() - added;
+        // pages from a later refresh or that came back empty leave gaps, an auction missing from
+        // them could just have moved pages.
+        let closed = if complete {
+            self.tracker
+                .lock()
+                .unwrap()
+                .close_missing(&auctions, scan_started)
+        } else {
+            println!("Full scan had pages from another refresh or empty pages, not closing missing auctions");
+            Vec::new()
         };
-        counter += 1;
-        println!("Finished 500 millisecond update");
-        tokio::time::sleep(Duration::from_millis(500)).await;
+        let closed_count = closed.len();
+        self.store
+            .upsert(&auctions, &RefreshColumns::full())
+            .await?;
+        self.mark_closed(&closed).await?;
+        changes.extend(closed);
+        // full scans write everything, catching anything the index got wrong.
+        self.written.lock().unwrap().record(&auctions);
+        self.book
+            .write()
+            .unwrap()
+            .extend(auctions.iter().cloned().map(AuctionModel::from));
+        self.shutdown.stats().record_full_scan();
+        self.shutdown.stats().record_written(auctions.len());
+        self.send(changes);
+        println!(
+            "Finished full scan of {} auctions in {:.2?}: {} added, {} changed, {} closed",
+            auctions.len(),
+            start.elapsed(),
+            added,
+            changed,
+            closed_count
+        );
+        Ok(())
+    }
+
+    async fn refresh_bazaar(&self) -> Result<(), AHScraperError> {
+        let bazaar = self.source.bazaar().await?;
+        *self.bazaar.lock().unwrap() = Arc::new(bazaar);
+        Ok(())
+    }
+
+    async fn ended_auctions(&self) -> Result<(), AHScraperError> {
+        let ended = self.source.ended_auctions().await?;
+        if self
+            .ended_last_updated
+            .swap(ended.last_updated, Ordering::Relaxed)
+            != ended.last_updated
+        {
+            let sold = self.tracker.lock().unwrap().sold(&ended.auctions);
+            self.mark_closed(&sold).await?;
+            // every sale is stored, including auctions listed before the tracker started.
+            self.store.record_sales(&ended.auctions).await?;
+            self.send(sold);
+        }
+        Ok(())
+    }
+
+    /// Ends the stored copies of auctions that closed early, which a later upsert of the same
+    /// auction wouldn't do since it's no longer listed, and drops them from the book and the
+    /// change index.
+    async fn mark_closed(&self, closed: &[AuctionEvent]) -> Result<(), AHScraperError> {
+        if closed.is_empty() {
+            return Ok(());
+        }
+        let uuids: Vec<String> = closed.iter().map(|event| event.uuid.clone()).collect();
+        {
+            let mut book = self.book.write().unwrap();
+            for uuid in &uuids {
+                book.remove(uuid);
+            }
+        }
+        self.written.lock().unwrap().forget(&uuids);
+        self.store.mark_closed(&uuids, self.source.now()).await?;
+        Ok(())
+    }
+
+    fn send(&self, changes: Vec<AuctionEvent>) {
+        for event in changes {
+            // only fails when nobody is subscribed.
+            let _ = self.events.send(event);
+        }
     }
 }
 
-async fn upsert_auctions(
+async fn analysis_task(
     db: Pool<AsyncPgConnection>,
-    auctions_values: Vec<Auction>,
-) -> Result<(), AHScraperError> {
-    let dbauctions: Vec<AuctionModel> = auctions_values
-        .into_iter()
-        .map(AuctionModel::from)
-        .collect::<Vec<_>>();
-    let mut conn = db.get().await?;
-    diesel::insert_into(auctions::table)
-        .values(&dbauctions)
-        .on_conflict(auctions::uuid)
-        .do_update()
-        .set((
-            auctions::last_updated.eq(excluded(auctions::last_updated)),
-            auctions::end_time.eq(excluded(auctions::end_time)),
-            auctions::claimed.eq(excluded(auctions::claimed)),
-        ))
-        .execute(&mut conn)
-        .await?;
-    Ok(())
+    config: AnalysisConfig,
+    every: Duration,
+    shutdown: Shutdown,
+    supervisor: Supervisor,
+) {
+    let config = &config;
+    let analysis = move || {
+        let db = db.clone();
+        async move {
+            let flagged = analysis::run_analysis(db, config).await?;
+            println!("Market analysis flagged {} new listings", flagged);
+            Ok(())
+        }
+    };
+    supervisor
+        .run_periodic("market analysis", every, &shutdown, analysis)
+        .await
 }
 
-async fn process_auctions_in_parallel(
+async fn sellers_task(
     db: Pool<AsyncPgConnection>,
-    auctions: Vec<Auction>,
-    chunk_size: usize,
-) -> Result<(), Box<AHScraperError>> {
-    let mut tasks = Vec::new();
+    every: Duration,
+    shutdown: Shutdown,
+    supervisor: Supervisor,
+) {
+    let refresh = move || {
+        let db = db.clone();
+        async move {
+            let count = sellers::refresh_sellers(db).await?;
+            println!("Refreshed stats for {} sellers", count);
+            Ok(())
+        }
+    };
+    supervisor
+        .run_periodic("seller refresh", every, &shutdown, refresh)
+        .await
+}
 
-    for chunk in auctions.chunks(chunk_size) {
-        let chunk = chunk.to_owned().to_vec(); // Clone the chunk data
-                                               // Clone the collection handle
-        let db_clone = db.clone();
-        let task = task::spawn(async move { upsert_auctions(db_clone, chunk).await });
+async fn partitions_task(
+    db: Pool<AsyncPgConnection>,
+    retention_months: Option<u32>,
+    expired: ExpiredPartitions,
+    every: Duration,
+    shutdown: Shutdown,
+    supervisor: Supervisor,
+) {
+    let maintain = move || {
+        let db = db.clone();
+        async move {
+            let report = partitions::maintain(db, retention_months, expired).await?;
+            print_maintenance(&report);
+            Ok(())
+        }
+    };
+    supervisor
+        .run_periodic("partition maintenance", every, &shutdown, maintain)
+        .await
+}
 
-        tasks.push(task);
-    }
+fn print_maintenance(report: &MaintenanceReport) {
+    println!(
+        "Partition maintenance moved {} ended auctions out of the hot partition, deleted {} past the retention, created {} partitions ({}), expired {} partitions ({})",
+        report.moved,
+        report.deleted,
+        report.created.len(),
+        report.created.join(", "),
+        report.expired.len(),
+        report.expired.join(", ")
+    );
+}
 
-    for task in tasks {
-        let _ = task.await?;
+async fn maintain_partitions_command(config: &Config) -> Result<(), AHScraperError> {
+    if store::sqlite_path(config.database_url()).is_some() {
+        return Err(AHScraperError::InvalidArgument(
+            "SQLite databases aren't partitioned".to_string(),
+        ));
     }
-    println!("Finished processing all auctions in parallel");
+    let pool = connect_for_writes(config).await?;
+    let report = partitions::maintain(
+        pool,
+        config.database.retention_months,
+        config.database.expired_partitions,
+    )
+    .await?;
+    print_maintenance(&report);
     Ok(())
 }
 
+async fn report_flips(
+    db: Pool<AsyncPgConnection>,
+    book: &RwLock<AuctionBook>,
+    bazaar: &Bazaar,
+    new_auctions: Vec<Auction>,
+    config: &FlipConfig,
+) -> Result<Vec<FlipCandidate>, AHScraperError> {
+    let flips = flips::find_flips(db, book, bazaar, new_auctions, config).await?;
+    for flip in &flips {
+        println!(
+            "Flip: {} ({}) listed at {} worth {} ({} in upgrades), profit {} ({:.0}% confidence) /viewauction {}",
+            flip.item_name,
+            flip.item_key,
+            flip.price,
+            flip.market_value,
+            flip.modifiers_value,
+            flip.expected_profit,
+            flip.confidence * 100.0,
+            flip.uuid
+        );
+    }
+    Ok(flips)
+}
+
 #[derive(Debug)]
-enum AHScraperError {
+pub enum AHScraperError {
     Reqwest(reqwest::Error),
     Env(env::VarError),
     DotEnv(dotenvy::Error),
@@ -120,6 +863,21 @@ enum AHScraperError {
     DieselConnection(diesel::ConnectionError),
     DieselPool(diesel_async::pooled_connection::bb8::RunError),
     DieselPoolConnection(diesel_async::pooled_connection::PoolError),
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "parquet")]
+    Arrow(arrow_schema::ArrowError),
+    Migration(Box<dyn Error + Send + Sync>),
+    Config(String),
+    Schema(String),
+    InvalidArgument(String),
+    Postgres(tokio_postgres::Error),
+    Tls(native_tls::Error),
+    /// A supervised job panicked.
+    Panic(String),
 }
 
 impl fmt::Display for AHScraperError {
@@ -145,6 +903,24 @@ impl fmt::Display for AHScraperError {
             AHScraperError::DieselPoolConnection(e) => {
                 write!(f, "Diesel Pool Connection Error: {}", e)
             }
+            AHScraperError::Io(e) => write!(f, "IO Error: {}", e),
+            AHScraperError::Json(e) => write!(f, "JSON Error: {}", e),
+            AHScraperError::Csv(e) => write!(f, "CSV Error: {}", e),
+            #[cfg(feature = "parquet")]
+            AHScraperError::Parquet(e) => write!(f, "Parquet Error: {}", e),
+            #[cfg(feature = "parquet")]
+            AHScraperError::Arrow(e) => write!(f, "Arrow Error: {}", e),
+            AHScraperError::Migration(e) => write!(f, "Migration Error: {}", e),
+            AHScraperError::Config(e) => write!(f, "Config error: {}", e),
+            AHScraperError::Schema(e) => write!(
+                f,
+                "Database schema doesn't match the models (run the migrate command): {}",
+                e
+            ),
+            AHScraperError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            AHScraperError::Postgres(e) => write!(f, "Postgres Error: {}", e),
+            AHScraperError::Tls(e) => write!(f, "TLS Error: {}", e),
+            AHScraperError::Panic(job) => write!(f, "{} panicked", job),
         }
     }
 }
@@ -210,3 +986,47 @@ impl From<diesel_async::pooled_connection::PoolError> for AHScraperError {
         AHScraperError::DieselPoolConnection(value)
     }
 }
+
+impl From<io::Error> for AHScraperError {
+    fn from(value: io::Error) -> Self {
+        AHScraperError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for AHScraperError {
+    fn from(value: serde_json::Error) -> Self {
+        AHScraperError::Json(value)
+    }
+}
+
+impl From<tokio_postgres::Error> for AHScraperError {
+    fn from(value: tokio_postgres::Error) -> Self {
+        AHScraperError::Postgres(value)
+    }
+}
+
+impl From<native_tls::Error> for AHScraperError {
+    fn from(value: native_tls::Error) -> Self {
+        AHScraperError::Tls(value)
+    }
+}
+
+impl From<csv::Error> for AHScraperError {
+    fn from(value: csv::Error) -> Self {
+        AHScraperError::Csv(value)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for AHScraperError {
+    fn from(value: parquet::errors::ParquetError) -> Self {
+        AHScraperError::Parquet(value)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<arrow_schema::ArrowError> for AHScraperError {
+    fn from(value: arrow_schema::ArrowError) -> Self {
+        AHScraperError::Arrow(value)
+    }
+}
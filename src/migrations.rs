@@ -0,0 +1,143 @@
+use crate::store::postgres::establish;
+use crate::AHScraperError;
+use diesel::sql_types::Text;
+use diesel::QueryableByName;
+use diesel_async::async_connection_wrapper::AsyncConnectionWrapper;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+/// Everything in `migrations/`, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// The tables the models are written against, checked against the live database.
+const SCHEMA: &str = include_str!("schema.rs");
+
+/// Applies every migration the database hasn't seen yet, returning the versions applied.
+pub async fn run_pending(database_url: &str) -> Result<Vec<String>, AHScraperError> {
+    // connected like the pool, so migrations go over TLS whenever the rest does.
+    let conn = establish(database_url).await?;
+    // the wrapper blocks on the async connection, which can't happen on a runtime thread.
+    tokio::task::spawn_blocking(move || {
+        let mut conn = AsyncConnectionWrapper::<AsyncPgConnection>::from(conn);
+        let applied = conn
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(AHScraperError::Migration)?;
+        Ok(applied.iter().map(|version| version.to_string()).collect())
+    })
+    .await?
+}
+
+struct ExpectedColumn {
+    table: &'static str,
+    column: &'static str,
+    /// Postgres' name for the type, `_text` for `text[]`.
+    udt_name: String,
+    nullable: bool,
+}
+
+/// Maps a diesel SQL type from `schema.rs` to its Postgres type name and nullability.
+fn postgres_type(sql_type: &str) -> (String, bool) {
+    if let Some(inner) = sql_type
+        .strip_prefix("Nullable<")
+        .and_then(|t| t.strip_suffix('>'))
+    {
+        return (postgres_type(inner).0, true);
+    }
+    if let Some(inner) = sql_type
+        .strip_prefix("Array<")
+        .and_then(|t| t.strip_suffix('>'))
+    {
+        // postgres doesn't track the nullability of array elements.
+        return (format!("_{}", postgres_type(inner).0), false);
+    }
+    let name = match sql_type {
+        "Int4" | "Integer" => "int4",
+        "Int8" | "BigInt" => "int8",
+        "Float8" | "Double" => "float8",
+        other => return (other.to_lowercase(), false),
+    };
+    (name.to_string(), false)
+}
+
+/// Every column declared in `schema.rs`.
+fn expected_columns() -> Vec<ExpectedColumn> {
+    let mut columns = Vec::new();
+    let mut table = None;
+    for line in SCHEMA.lines().map(str::trim) {
+        if line.starts_with("diesel::") || line.starts_with("//") {
+            continue;
+        }
+        if let Some((column, sql_type)) = line.trim_end_matches(',').split_once(" -> ") {
+            if let Some(table) = table {
+                let (udt_name, nullable) = postgres_type(sql_type);
+                columns.push(ExpectedColumn {
+                    table,
+                    column,
+                    udt_name,
+                    nullable,
+                });
+            }
+        } else if line.ends_with('{') {
+            table = line.split_whitespace().next();
+        }
+    }
+    columns
+}
+
+#[derive(QueryableByName)]
+struct LiveColumn {
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Text)]
+    column_name: String,
+    #[diesel(sql_type = Text)]
+    udt_name: String,
+    #[diesel(sql_type = Text)]
+    is_nullable: String,
+}
+
+/// Checks that every table and column in `schema.rs` exists in the database with the same type
+/// and nullability, so a missed migration fails on startup instead of on the first write.
+pub async fn verify_schema(conn: &mut AsyncPgConnection) -> Result<(), AHScraperError> {
+    let live: Vec<LiveColumn> = diesel::sql_query(
+        "SELECT table_name::text, column_name::text, udt_name::text, is_nullable::text \
+         FROM information_schema.columns WHERE table_schema = current_schema()",
+    )
+    .load(conn)
+    .await?;
+    let mut problems = Vec::new();
+    for expected in expected_columns() {
+        let found = live
+            .iter()
+            .find(|c| c.table_name == expected.table && c.column_name == expected.column);
+        let Some(found) = found else {
+            problems.push(format!("{}.{} is missing", expected.table, expected.column));
+            continue;
+        };
+        if found.udt_name != expected.udt_name {
+            problems.push(format!(
+                "{}.{} is {} in the database but {} in schema.rs",
+                expected.table, expected.column, found.udt_name, expected.udt_name
+            ));
+        }
+        let nullable = found.is_nullable == "YES";
+        if nullable != expected.nullable {
+            problems.push(format!(
+                "{}.{} is {} in the database but {} in schema.rs",
+                expected.table,
+                expected.column,
+                if nullable { "nullable" } else { "not null" },
+                if expected.nullable {
+                    "nullable"
+                } else {
+                    "not null"
+                },
+            ));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AHScraperError::Schema(problems.join(", ")))
+    }
+}
@@ -1,10 +1,9 @@
 use std::time::SystemTime;
 
-use crate::hypixel_api::{
-    auction::Auction as APIAuction,
-    item::{Gem, MinecraftEnchant},
-};
+use crate::hypixel_api::{auction::Auction as APIAuction, item::Gem};
 use diesel::prelude::*;
+use serde::Serialize;
+use serde_with::{serde_as, TimestampMilliSeconds};
 
 #[derive(Queryable, Selectable, Insertable)]
 #[diesel(table_name = crate::schema::auctions)]
@@ -52,6 +51,168 @@ pub struct Auction {
     pub expertise_kills: Option<i32>,
     pub runes: Option<Vec<String>>,
     pub farmed_cultivating: Option<i32>,
+    pub item_key: Option<String>,
+    pub highest_bid: Option<i64>,
+}
+
+/// Every column of an auction exactly as stored, nullable wherever the table is.
+#[serde_as]
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::schema::auctions)]
+pub struct AuctionRecord {
+    pub uuid: String,
+    pub auctioneer: String,
+    pub profile_id: String,
+    pub coop: Option<Vec<Option<String>>>,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub start_time: SystemTime,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub end_time: SystemTime,
+    pub item_name: String,
+    pub item_uuid: Option<String>,
+    pub item_lore: Option<String>,
+    pub item_id: Option<String>,
+    pub item_count: Option<i32>,
+    pub item_damage: Option<i32>,
+    pub enchantments: Option<Vec<Option<String>>>,
+    pub unbreakable: Option<bool>,
+    pub price: i64,
+    pub claimed: Option<bool>,
+    pub tier: String,
+    pub category: String,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub last_updated: SystemTime,
+    pub bin: bool,
+    pub reforge: Option<String>,
+    pub upgrade_level: Option<i32>,
+    pub hot_potato_count: Option<i32>,
+    pub recomb: Option<bool>,
+    pub unlocked_gem_slots: Option<Vec<Option<String>>>,
+    pub slotted_gems: Option<Vec<Option<String>>>,
+    pub pet_active: Option<bool>,
+    pub pet_type: Option<String>,
+    pub pet_held_item: Option<String>,
+    pub pet_exp: Option<i32>,
+    pub pet_candy_used: Option<i32>,
+    pub dungeon_item_level: Option<i32>,
+    pub red_armor_coloring: Option<i32>,
+    pub green_armor_coloring: Option<i32>,
+    pub blue_armor_coloring: Option<i32>,
+    pub anvil_uses: Option<i32>,
+    pub pelts_earned: Option<i32>,
+    pub champion_combat_xp: Option<i32>,
+    pub compact_blocks: Option<i32>,
+    pub hecatomb_s_runs: Option<i32>,
+    pub expertise_kills: Option<i32>,
+    pub runes: Option<Vec<Option<String>>>,
+    pub farmed_cultivating: Option<i32>,
+    pub item_key: Option<String>,
+    pub buyer: Option<String>,
+    pub sold_price: Option<i64>,
+    pub highest_bid: Option<i64>,
+}
+
+/// The columns of an auction worth showing to API consumers, loadable as is from the table.
+#[serde_as]
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::schema::auctions)]
+pub struct AuctionSummary {
+    pub uuid: String,
+    pub auctioneer: String,
+    pub profile_id: String,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub start_time: SystemTime,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub end_time: SystemTime,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub last_updated: SystemTime,
+    pub item_name: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub item_uuid: Option<String>,
+    pub item_lore: Option<String>,
+    pub tier: String,
+    pub category: String,
+    pub price: i64,
+    pub highest_bid: Option<i64>,
+    pub bin: bool,
+    pub claimed: Option<bool>,
+    pub reforge: Option<String>,
+    pub upgrade_level: Option<i32>,
+    pub hot_potato_count: Option<i32>,
+    pub recomb: Option<bool>,
+    pub enchantments: Option<Vec<Option<String>>>,
+    pub slotted_gems: Option<Vec<Option<String>>>,
+    pub runes: Option<Vec<Option<String>>>,
+    pub pet_type: Option<String>,
+    pub pet_exp: Option<i32>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::market_flags)]
+pub struct MarketFlag {
+    pub id: i32,
+    pub kind: String,
+    pub auction_uuid: String,
+    pub related_auction_uuid: Option<String>,
+    pub item_key: Option<String>,
+    pub auctioneer: String,
+    pub profile_id: String,
+    pub price: i64,
+    pub reference_price: Option<i64>,
+    pub detected_at: SystemTime,
+    pub reviewed: bool,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::saved_searches)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub name: String,
+    pub webhook_url: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub name_contains: Option<String>,
+    pub tier: Option<String>,
+    pub min_stars: Option<i32>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
+    pub bin_only: bool,
+    pub enabled: bool,
+    pub created_at: SystemTime,
+    /// `json` or `discord`, see [`crate::saved_searches::PayloadFormat`].
+    pub payload_format: String,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::saved_searches, treat_none_as_null = true)]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub webhook_url: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub name_contains: Option<String>,
+    pub tier: Option<String>,
+    pub min_stars: Option<i32>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
+    pub bin_only: bool,
+    pub payload_format: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::sellers)]
+pub struct Seller {
+    pub auctioneer: String,
+    pub profile_id: String,
+    pub coop: Option<Vec<Option<String>>>,
+    pub active_listings: i64,
+    pub total_listed_value: i64,
+    pub sold_count: i64,
+    /// Median of price / lowest BIN - 1 over the seller's open BIN listings.
+    pub median_markup: Option<f64>,
+    pub categories: Vec<Option<String>>,
+    pub refreshed_at: SystemTime,
 }
 
 impl From<APIAuction> for Auction {
@@ -108,10 +269,8 @@ impl From<APIAuction> for Auction {
                     .filter(|(_name, gem)| matches!(gem, &Gem::UnlockedSlot(_)))
                     .collect::<Vec<(&String, &Gem)>>();
                 let x = temp.first();
-                if let Some((_name, gem)) = x {
-                    if let Gem::UnlockedSlot(slots) = gem {
-                        unlocked_gems_slots = Some(slots.clone());
-                    }
+                if let Some((_name, Gem::UnlockedSlot(slots))) = x {
+                    unlocked_gems_slots = Some(slots.clone());
                 };
             }
 
@@ -175,6 +334,9 @@ impl From<APIAuction> for Auction {
             hecatomb_s_runs = item.tag.extra_attributes.hecatomb_s_runs.map(|x| x as i32);
             expertise_kills = item.tag.extra_attributes.expertise_kills.map(|x| x as i32);
         }
+        let item_key = item_id
+            .as_deref()
+            .map(|id| item_key(id, pet_type.as_deref(), &value.tier, enchs.as_deref()));
         Auction {
             uuid: value.uuid,
             auctioneer: value.auctioneer,
@@ -219,6 +381,94 @@ impl From<APIAuction> for Auction {
             hecatomb_s_runs,
             expertise_kills,
             runes,
+            item_key,
+            highest_bid: Some(value.highest_bid_amount),
         }
     }
 }
+
+/// [`item_key`] of an API auction, without converting the whole auction.
+pub fn item_key_for(auction: &APIAuction) -> Option<String> {
+    let attributes = &auction.item_data.as_ref()?.tag.extra_attributes;
+    let enchantments = attributes.enchantments.as_ref().map(|enchantments| {
+        enchantments
+            .iter()
+            .map(|(name, level)| format!("{} {}", name, level))
+            .collect::<Vec<_>>()
+    });
+    Some(item_key(
+        &attributes.id,
+        attributes.pet_info.as_ref().map(|p| p.p_type.as_str()),
+        &auction.tier,
+        enchantments.as_deref(),
+    ))
+}
+
+/// Builds the key auctions are grouped by when comparing prices. Most items are keyed by their
+/// skyblock id, but pets are split by type and rarity and single enchant books by the enchant,
+/// since those share an id while being priced completely differently.
+pub fn item_key(
+    item_id: &str,
+    pet_type: Option<&str>,
+    tier: &str,
+    enchantments: Option<&[String]>,
+) -> String {
+    match (item_id, pet_type, enchantments) {
+        ("PET", Some(pet_type), _) => format!("PET_{}_{}", pet_type, tier),
+        ("ENCHANTED_BOOK", _, Some([ench])) => {
+            format!("ENCHANTED_BOOK_{}", ench.replace(' ', "_").to_uppercase())
+        }
+        _ => item_id.to_string(),
+    }
+}
+
+impl From<&Auction> for AuctionSummary {
+    fn from(a: &Auction) -> Self {
+        // arrays are only nullable element-wise in the table.
+        let nullable = |values: &Option<Vec<String>>| {
+            values
+                .as_ref()
+                .map(|values| values.iter().cloned().map(Some).collect())
+        };
+        AuctionSummary {
+            uuid: a.uuid.clone(),
+            auctioneer: a.auctioneer.clone(),
+            profile_id: a.profile_id.clone(),
+            start_time: a.start_time,
+            end_time: a.end_time,
+            last_updated: a.last_updated,
+            item_name: a.item_name.clone(),
+            item_id: a.item_id.clone(),
+            item_key: a.item_key.clone(),
+            item_uuid: a.item_uuid.clone(),
+            item_lore: a.item_lore.clone(),
+            tier: a.tier.clone(),
+            category: a.category.clone(),
+            price: a.price,
+            highest_bid: a.highest_bid,
+            bin: a.bin,
+            claimed: a.claimed,
+            reforge: a.reforge.clone(),
+            upgrade_level: a.upgrade_level,
+            hot_potato_count: a.hot_potato_count,
+            recomb: a.recomb,
+            enchantments: nullable(&a.enchantments),
+            slotted_gems: nullable(&a.slotted_gems),
+            runes: nullable(&a.runes),
+            pet_type: a.pet_type.clone(),
+            pet_exp: a.pet_exp,
+        }
+    }
+}
+
+/// A listing of `item_key` with no item data, for tests. It started at the epoch and ends an
+/// hour later.
+#[cfg(test)]
+pub fn test_auction(uuid: &str, item_key: &str, price: i64, bin: bool) -> Auction {
+    let mut api = crate::hypixel_api::auction::test_auction(uuid, price, bin);
+    api.item_name = item_key.to_string();
+    let mut auction = Auction::from(api);
+    auction.item_id = Some(item_key.to_string());
+    auction.item_key = Some(item_key.to_string());
+    auction
+}
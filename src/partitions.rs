@@ -0,0 +1,263 @@
+use crate::AHScraperError;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text};
+use diesel::QueryableByName;
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl, SimpleAsyncConnection};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What happens to a month of auctions once it's past the retention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiredPartitions {
+    /// Detached from `auctions` but kept as a table of its own, to dump or drop by hand.
+    #[default]
+    Detach,
+    /// Deleted.
+    Drop,
+}
+
+impl FromStr for ExpiredPartitions {
+    type Err = AHScraperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "detach" => Ok(ExpiredPartitions::Detach),
+            "drop" => Ok(ExpiredPartitions::Drop),
+            _ => Err(AHScraperError::InvalidArgument(format!(
+                "unknown expired partition action {} (expected detach or drop)",
+                s
+            ))),
+        }
+    }
+}
+
+/// What a maintenance run changed.
+#[derive(Debug, Default)]
+pub struct MaintenanceReport {
+    /// Month partitions split out of the hot partition.
+    pub created: Vec<String>,
+    /// Auctions moved out of the hot partition.
+    pub moved: i64,
+    /// Auctions in the hot partition that were already past the retention and got dropped.
+    pub deleted: i64,
+    /// Month partitions dropped or detached, including months already past the retention that
+    /// went straight to a detached table.
+    pub expired: Vec<String>,
+}
+
+/// A past month, all of its auctions ended, still in the hot partition.
+#[derive(QueryableByName)]
+struct HotMonth {
+    /// `auctions_YYYY_MM`
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    starts: String,
+    #[diesel(sql_type = Text)]
+    ends: String,
+    #[diesel(sql_type = BigInt)]
+    auctions: i64,
+    /// Already past the retention, so it's never attached.
+    #[diesel(sql_type = Bool)]
+    expired: bool,
+}
+
+#[derive(QueryableByName)]
+struct Partition {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+/// The first month that is kept, `retention_months` before the current one, or every month
+/// without a retention.
+const CUTOFF: &str = "coalesce(date_trunc('month', now() AT TIME ZONE 'UTC') \
+                      - make_interval(months => $1), '-infinity')";
+
+/// The first month that is kept, the same as [`CUTOFF`].
+pub fn retention_cutoff(retention_months: u32, now: SystemTime) -> SystemTime {
+    let now = DateTime::<Utc>::from(now);
+    let month = NaiveDate::from_ymd_opt(now.year(), now.month(), 1)
+        .and_then(|month| month.checked_sub_months(Months::new(retention_months)))
+        .unwrap_or(NaiveDate::MIN);
+    month.and_time(Default::default()).and_utc().into()
+}
+
+/// Whether an auction belongs to a month that is past the retention and over, so already
+/// dropped or detached. Writing it again would only put it back in `auctions_hot`.
+pub fn past_retention(
+    start: SystemTime,
+    end: SystemTime,
+    retention_months: Option<u32>,
+    now: SystemTime,
+) -> bool {
+    retention_months.is_some_and(|months| start < retention_cutoff(months, now) && end <= now)
+}
+
+/// Every past month in `auctions_hot` whose auctions have all ended, oldest first.
+async fn hot_months(
+    conn: &mut AsyncPgConnection,
+    retention_months: Option<i32>,
+) -> Result<Vec<HotMonth>, AHScraperError> {
+    Ok(diesel::sql_query(format!(
+        "SELECT 'auctions_' || to_char(month, 'YYYY_MM') AS name, \
+                month::text AS starts, \
+                (month + interval '1 month')::text AS ends, \
+                auctions, \
+                month < {} AS expired \
+         FROM ( \
+            SELECT date_trunc('month', start_time) AS month, count(*) AS auctions \
+            FROM auctions_hot \
+            WHERE start_time < date_trunc('month', now() AT TIME ZONE 'UTC') \
+            GROUP BY month \
+            HAVING max(end_time) <= now() AT TIME ZONE 'UTC' \
+         ) months ORDER BY month",
+        CUTOFF
+    ))
+    .bind::<Nullable<Integer>, _>(retention_months)
+    .load(conn)
+    .await?)
+}
+
+/// Moves every past month whose auctions have all ended out of `auctions_hot` into its own
+/// partition, then drops or detaches month partitions older than `retention_months` months
+/// before the current one. Writes to the hot partition wait while a month is moved.
+///
+/// Auctions go by the month they were listed in, not the month they ended in. `end_time` moves
+/// when an auction sells early, and with it in the key an upsert would stop conflicting with the
+/// row it wrote before; `start_time` never changes.
+pub async fn maintain(
+    db: Pool<AsyncPgConnection>,
+    retention_months: Option<u32>,
+    expired: ExpiredPartitions,
+) -> Result<MaintenanceReport, AHScraperError> {
+    let mut conn = db.get().await?;
+    let retention_months = retention_months.map(|months| months as i32);
+    let mut report = MaintenanceReport::default();
+    let months = hot_months(&mut conn, retention_months).await?;
+    for month in months {
+        // a batch runs as one transaction, so the rows are never in both places or neither.
+        let sql = if month.expired && expired == ExpiredPartitions::Drop {
+            format!(
+                "DELETE FROM auctions_hot WHERE start_time >= '{0}' AND start_time < '{1}'",
+                month.starts, month.ends
+            )
+        } else {
+            // an expired month that was detached before gets the stragglers added to it, minus
+            // any it already has.
+            let attach = if month.expired {
+                String::new()
+            } else {
+                format!(
+                    "ALTER TABLE auctions ATTACH PARTITION {0} FOR VALUES FROM ('{1}') TO ('{2}');",
+                    month.name, month.starts, month.ends
+                )
+            };
+            format!(
+                "CREATE TABLE IF NOT EXISTS {0} (LIKE auctions INCLUDING DEFAULTS); \
+                 WITH moved AS ( \
+                    DELETE FROM auctions_hot WHERE start_time >= '{1}' AND start_time < '{2}' \
+                    RETURNING * \
+                 ) INSERT INTO {0} SELECT * FROM moved ON CONFLICT DO NOTHING; \
+                 {3}",
+                month.name, month.starts, month.ends, attach
+            )
+        };
+        conn.batch_execute(&sql).await?;
+        if month.expired && expired == ExpiredPartitions::Drop {
+            report.deleted += month.auctions;
+        } else {
+            report.moved += month.auctions;
+        }
+        if !month.expired {
+            report.created.push(month.name);
+        } else if expired == ExpiredPartitions::Detach {
+            report.expired.push(month.name);
+        }
+    }
+    let partitions: Vec<Partition> = diesel::sql_query(format!(
+        "SELECT c.relname::text AS name \
+         FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid \
+         WHERE i.inhparent = 'auctions'::regclass \
+           AND c.relname ~ '^auctions_[0-9]{{4}}_[0-9]{{2}}$' \
+           AND to_date(substring(c.relname from 10), 'YYYY_MM') < {} \
+         ORDER BY name",
+        CUTOFF
+    ))
+    .bind::<Nullable<Integer>, _>(retention_months)
+    .load(&mut conn)
+    .await?;
+    for partition in partitions {
+        let sql = match expired {
+            ExpiredPartitions::Detach => {
+                format!("ALTER TABLE auctions DETACH PARTITION {}", partition.name)
+            }
+            ExpiredPartitions::Drop => format!("DROP TABLE {}", partition.name),
+        };
+        conn.batch_execute(&sql).await?;
+        report.expired.push(partition.name);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::postgres::establish;
+    use diesel_async::AsyncConnection;
+    use std::time::Duration;
+
+    fn at(date: &str) -> SystemTime {
+        DateTime::parse_from_rfc3339(date).unwrap().into()
+    }
+
+    #[test]
+    fn retention_counts_back_from_the_current_month() {
+        let now = at("2024-03-15T12:00:00Z");
+        assert_eq!(retention_cutoff(0, now), at("2024-03-01T00:00:00Z"));
+        assert_eq!(retention_cutoff(3, now), at("2023-12-01T00:00:00Z"));
+        let listed = at("2023-11-30T00:00:00Z");
+        let ended = listed + Duration::from_secs(60 * 60);
+        assert!(past_retention(listed, ended, Some(3), now));
+        assert!(!past_retention(
+            listed,
+            now + Duration::from_secs(1),
+            Some(3),
+            now
+        ));
+        assert!(!past_retention(listed, ended, Some(4), now));
+        assert!(!past_retention(listed, ended, None, now));
+    }
+
+    /// Needs a Postgres at `TEST_DATABASE_URL`, a temporary `auctions_hot` stands in for the
+    /// real one and goes away with the test transaction.
+    #[tokio::test]
+    async fn hot_months_are_ended_past_months_split_at_the_retention() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("TEST_DATABASE_URL isn't set, skipping");
+            return;
+        };
+        let mut conn = establish(&url).await.unwrap();
+        conn.begin_test_transaction().await.unwrap();
+        conn.batch_execute(
+            "CREATE TEMP TABLE auctions_hot (start_time timestamp, end_time timestamp); \
+             INSERT INTO auctions_hot \
+             SELECT month + interval '1 day', month + interval '2 days' + extra \
+             FROM (VALUES (3, interval '0'), (3, interval '0'), (2, interval '0'), \
+                          (2, interval '1 year'), (1, interval '0'), (0, interval '0')) \
+                  AS t(months_ago, extra), \
+             LATERAL (SELECT date_trunc('month', now() AT TIME ZONE 'UTC') \
+                             - make_interval(months => months_ago) AS month) m",
+        )
+        .await
+        .unwrap();
+        let months = hot_months(&mut conn, Some(2)).await.unwrap();
+        let months: Vec<(i64, bool)> = months.iter().map(|m| (m.auctions, m.expired)).collect();
+        // two months back still has a live auction, this month is never split out.
+        assert_eq!(months, [(2, true), (1, false)]);
+        let months = hot_months(&mut conn, None).await.unwrap();
+        assert!(months.iter().all(|month| !month.expired));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::models::AuctionSummary;
+use crate::schema::auctions;
+use crate::AHScraperError;
+use diesel::{ExpressionMethods, PgArrayExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use std::time::{Duration, SystemTime};
+
+/// Highest enchantment level worth asking for, ultimates and stacking enchants included.
+const MAX_ENCHANTMENT_LEVEL: u32 = 10;
+/// Runes only go up to level 3.
+const MAX_RUNE_LEVEL: u32 = 3;
+
+/// Open BIN listings of an item key, cheapest first.
+pub async fn open_bins(
+    conn: &mut AsyncPgConnection,
+    item_key: &str,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    Ok(auctions::table
+        .filter(auctions::item_key.eq(item_key))
+        .filter(auctions::bin.eq(true))
+        .filter(auctions::end_time.gt(SystemTime::now()))
+        .order(auctions::price.asc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Open auctions of an item id, whatever their upgrades, cheapest first.
+pub async fn by_item_id(
+    conn: &mut AsyncPgConnection,
+    item_id: &str,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    Ok(auctions::table
+        .filter(auctions::item_id.eq(item_id))
+        .filter(auctions::end_time.gt(SystemTime::now()))
+        .order(auctions::price.asc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Everything a player has listed, most recently updated first.
+pub async fn by_seller(
+    conn: &mut AsyncPgConnection,
+    auctioneer: &str,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    Ok(auctions::table
+        .filter(auctions::auctioneer.eq(auctioneer))
+        .order(auctions::last_updated.desc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Every auction an individual item was listed in, oldest first.
+pub async fn item_history(
+    conn: &mut AsyncPgConnection,
+    item_uuid: &str,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    Ok(auctions::table
+        .filter(auctions::item_uuid.eq(item_uuid))
+        .order(auctions::start_time.asc())
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Open auctions ending within `within`, soonest first.
+pub async fn ending_soon(
+    conn: &mut AsyncPgConnection,
+    within: Duration,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    let now = SystemTime::now();
+    Ok(auctions::table
+        .filter(auctions::end_time.gt(now))
+        .filter(auctions::end_time.le(now + within))
+        .order(auctions::end_time.asc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Open auctions with the enchantment at `min_level` or above, cheapest first. `enchantment` is
+/// the id the item data uses, e.g. `ultimate_wise`.
+pub async fn with_enchantment(
+    conn: &mut AsyncPgConnection,
+    enchantment: &str,
+    min_level: u32,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    let entries = levels(enchantment, min_level, MAX_ENCHANTMENT_LEVEL);
+    Ok(auctions::table
+        .filter(auctions::enchantments.overlaps_with(entries))
+        .filter(auctions::end_time.gt(SystemTime::now()))
+        .order(auctions::price.asc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Open auctions with the rune at `min_level` or above, cheapest first. `rune` is the id the item
+/// data uses, e.g. `MUSIC`.
+pub async fn with_rune(
+    conn: &mut AsyncPgConnection,
+    rune: &str,
+    min_level: u32,
+    limit: i64,
+) -> Result<Vec<AuctionSummary>, AHScraperError> {
+    let entries = levels(rune, min_level, MAX_RUNE_LEVEL);
+    Ok(auctions::table
+        .filter(auctions::runes.overlaps_with(entries))
+        .filter(auctions::end_time.gt(SystemTime::now()))
+        .order(auctions::price.asc())
+        .limit(limit)
+        .select(AuctionSummary::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// The `"name level"` entries the arrays store for every level from `min` to `max`, so a level
+/// range is one overlap (`&&`) the GIN index can answer.
+fn levels(name: &str, min: u32, max: u32) -> Vec<Option<String>> {
+    (min.max(1)..=max)
+        .map(|level| Some(format!("{} {}", name, level)))
+        .collect()
+}
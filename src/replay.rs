@@ -0,0 +1,166 @@
+use crate::archive::{self, RetentionPolicy, SnapshotArchiver};
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::hypixel_api::ended::EndedAuctions;
+use crate::hypixel_api::page::Page;
+use crate::hypixel_api::source::PageSource;
+use crate::AHScraperError;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct ReplayState {
+    started: Option<Instant>,
+    /// Index into `snapshots` of the refresh currently being served.
+    current: Option<usize>,
+}
+
+/// Serves auction pages recorded by the [`SnapshotArchiver`], moving to the next snapshot each
+/// time a scan starts over from page 0.
+///
+/// With a `speed` above zero snapshots are spaced out like they were recorded, sped up by that
+/// factor, so a speed of 60 replays an hour of archive in a minute. A speed of zero moves on to
+/// the next snapshot every scan.
+pub struct ReplayPageSource {
+    archive: SnapshotArchiver,
+    snapshots: Vec<u64>,
+    speed: f64,
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayPageSource {
+    pub fn new(dir: impl Into<PathBuf>, speed: f64) -> Result<Self, AHScraperError> {
+        let archive = SnapshotArchiver::new(dir, RetentionPolicy::default());
+        let snapshots = archive.snapshots()?;
+        if snapshots.is_empty() {
+            return Err(AHScraperError::InvalidArgument(format!(
+                "no archived snapshots in {}",
+                archive.dir().display()
+            )));
+        }
+        Ok(ReplayPageSource {
+            archive,
+            snapshots,
+            speed,
+            state: Mutex::new(ReplayState::default()),
+        })
+    }
+
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Picks the snapshot the next scan reads from, returning its `lastUpdated`.
+    fn advance(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let next = match state.current {
+            None => {
+                state.started = Some(Instant::now());
+                0
+            }
+            Some(current) if self.speed <= 0.0 => current + 1,
+            Some(current) => {
+                let elapsed = state.started.map_or(0.0, |s| s.elapsed().as_secs_f64());
+                let replay_time = self.snapshots[0] + (elapsed * self.speed * 1000.0) as u64;
+                let reached = self.snapshots.partition_point(|&s| s <= replay_time);
+                current.max(reached.saturating_sub(1))
+            }
+        };
+        let next = next.min(self.snapshots.len() - 1);
+        state.current = Some(next);
+        self.snapshots[next]
+    }
+
+    fn current(&self) -> u64 {
+        let current = self.state.lock().unwrap().current.unwrap_or(0);
+        self.snapshots[current]
+    }
+}
+
+impl PageSource for ReplayPageSource {
+    fn page(&self, page: u32) -> BoxFuture<'_, Result<Page, AHScraperError>> {
+        let last_updated = if page == 0 {
+            self.advance()
+        } else {
+            self.current()
+        };
+        self.page_in(page, last_updated)
+    }
+
+    /// Scans running side by side each move to a newer snapshot from page 0, so the rest of a
+    /// scan reads the snapshot its page 0 came from rather than the current one.
+    fn page_in(&self, page: u32, last_updated: u64) -> BoxFuture<'_, Result<Page, AHScraperError>> {
+        let path = self.archive.page_path(last_updated, page);
+        async move {
+            // quick scans only archive their first few pages, the rest of that refresh was never
+            // seen by the scraper.
+            if !path.exists() {
+                return Ok(Page {
+                    success: true,
+                    page,
+                    total_pages: 0,
+                    total_auctions: 0,
+                    last_updated,
+                    auctions: Vec::new(),
+                });
+            }
+            let body = tokio::task::spawn_blocking(move || archive::read_page(&path)).await??;
+            Ok(serde_json::from_slice::<Page>(&body)?)
+        }
+        .boxed()
+    }
+
+    /// The bazaar isn't archived, so modifiers are valued from the AH alone.
+    fn bazaar(&self) -> BoxFuture<'_, Result<Bazaar, AHScraperError>> {
+        async { Ok(Bazaar::default()) }.boxed()
+    }
+
+    fn ended_auctions(&self) -> BoxFuture<'_, Result<EndedAuctions, AHScraperError>> {
+        async { Ok(EndedAuctions::default()) }.boxed()
+    }
+
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.current())
+    }
+
+    fn finished(&self) -> bool {
+        self.state.lock().unwrap().current == Some(self.snapshots.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hypixel_api::auction::test_auction;
+
+    #[tokio::test]
+    async fn a_scan_reads_the_snapshot_its_first_page_came_from() {
+        let dir = std::env::temp_dir().join(format!("ahscraper-replay-{}", std::process::id()));
+        let archive = SnapshotArchiver::new(&dir, RetentionPolicy::default());
+        for last_updated in [1000, 2000] {
+            for page in 0..2 {
+                let body = serde_json::json!({
+                    "success": true,
+                    "page": page,
+                    "totalPages": 2,
+                    "totalAuctions": 2,
+                    "lastUpdated": last_updated,
+                    "auctions": [test_auction(&format!("{}-{}", last_updated, page), 1, true)],
+                });
+                archive
+                    .store(last_updated, page, body.to_string().as_bytes())
+                    .unwrap();
+            }
+        }
+        let source = ReplayPageSource::new(&dir, 0.0).unwrap();
+        let full_scan = source.page(0).await.unwrap();
+        // a quick scan starting in the middle of the full scan moves the replay on.
+        assert_eq!(source.page(0).await.unwrap().last_updated, 2000);
+        let page = source.page_in(1, full_scan.last_updated).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(page.last_updated, 1000);
+        assert_eq!(page.auctions[0].uuid, "1000-1");
+    }
+}
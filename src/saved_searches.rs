@@ -0,0 +1,467 @@
+use crate::book::AuctionBook;
+use crate::discord;
+use crate::models::{Auction as AuctionModel, NewSavedSearch, SavedSearch};
+use crate::schema::{saved_search_hits, saved_searches};
+use crate::webhooks::WebhookDispatcher;
+use crate::AHScraperError;
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::Serialize;
+use std::sync::RwLock;
+
+/// How a search's hits are rendered before being posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// The [`SearchHit`] as is.
+    Json,
+    /// A Discord webhook embed, see [`discord::auction_message`].
+    Discord,
+}
+
+impl PayloadFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            PayloadFormat::Discord => "discord",
+        }
+    }
+}
+
+/// What gets posted to a search's webhook for each matching listing.
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub search_id: i32,
+    pub search_name: String,
+    pub uuid: String,
+    pub item_name: String,
+    pub item_id: Option<String>,
+    pub item_key: Option<String>,
+    pub tier: String,
+    pub price: i64,
+    pub bin: bool,
+    pub view_command: String,
+}
+
+impl SearchHit {
+    pub fn new(search: &SavedSearch, auction: &AuctionModel) -> Self {
+        SearchHit {
+            search_id: search.id,
+            search_name: search.name.clone(),
+            uuid: auction.uuid.clone(),
+            item_name: auction.item_name.clone(),
+            item_id: auction.item_id.clone(),
+            item_key: auction.item_key.clone(),
+            tier: auction.tier.clone(),
+            price: auction.price,
+            bin: auction.bin,
+            view_command: format!("/viewauction {}", auction.uuid),
+        }
+    }
+}
+
+impl SavedSearch {
+    pub fn format(&self) -> PayloadFormat {
+        match self.payload_format.as_str() {
+            "discord" => PayloadFormat::Discord,
+            _ => PayloadFormat::Json,
+        }
+    }
+
+    pub fn matches(&self, auction: &AuctionModel) -> bool {
+        if self.bin_only && !auction.bin {
+            return false;
+        }
+        if self.item_id.is_some() && self.item_id != auction.item_id {
+            return false;
+        }
+        if self.item_key.is_some() && self.item_key != auction.item_key {
+            return false;
+        }
+        if let Some(needle) = &self.name_contains {
+            if !auction
+                .item_name
+                .to_lowercase()
+                .contains(&needle.to_lowercase())
+            {
+                return false;
+            }
+        }
+        if let Some(tier) = &self.tier {
+            if !auction.tier.eq_ignore_ascii_case(tier) {
+                return false;
+            }
+        }
+        if let Some(min_stars) = self.min_stars {
+            // Older items keep their stars in dungeon_item_level, newer ones in upgrade_level.
+            let stars = auction
+                .upgrade_level
+                .max(auction.dungeon_item_level)
+                .unwrap_or(0);
+            if stars < min_stars {
+                return false;
+            }
+        }
+        if self.min_price.is_some_and(|min| auction.price < min) {
+            return false;
+        }
+        self.max_price.is_none_or(|max| auction.price <= max)
+    }
+}
+
+pub async fn create_saved_search(
+    conn: &mut AsyncPgConnection,
+    search: &NewSavedSearch,
+) -> Result<SavedSearch, AHScraperError> {
+    Ok(diesel::insert_into(saved_searches::table)
+        .values(search)
+        .returning(SavedSearch::as_returning())
+        .get_result(conn)
+        .await?)
+}
+
+/// Creates or updates a search for each rule, matching on name, returning how many were created.
+/// Searches whose rule was removed are left alone.
+pub async fn sync_rules(
+    conn: &mut AsyncPgConnection,
+    rules: &[NewSavedSearch],
+) -> Result<usize, AHScraperError> {
+    let mut created = 0;
+    for rule in rules {
+        let updated = diesel::update(saved_searches::table)
+            .filter(saved_searches::name.eq(&rule.name))
+            .set((rule, saved_searches::enabled.eq(true)))
+            .execute(conn)
+            .await?;
+        if updated == 0 {
+            create_saved_search(conn, rule).await?;
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+pub async fn delete_saved_search(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Result<(), AHScraperError> {
+    diesel::delete(saved_searches::table.find(id))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn enabled_searches(
+    conn: &mut AsyncPgConnection,
+) -> Result<Vec<SavedSearch>, AHScraperError> {
+    Ok(saved_searches::table
+        .filter(saved_searches::enabled.eq(true))
+        .select(SavedSearch::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Remembers which auctions went to which search, so each is posted once.
+pub trait HitLog: Clone + Send + Sync + 'static {
+    /// Claims a hit before it's sent, returning false if it already was.
+    fn claim<'a>(
+        &'a self,
+        search_id: i32,
+        auction_uuid: &'a str,
+    ) -> BoxFuture<'a, Result<bool, AHScraperError>>;
+
+    /// Gives back the hit of a delivery that gave up, so only delivered hits stay claimed.
+    fn release<'a>(
+        &'a self,
+        search_id: i32,
+        auction_uuid: &'a str,
+    ) -> BoxFuture<'a, Result<(), AHScraperError>>;
+}
+
+/// Hits are kept in `saved_search_hits`.
+impl HitLog for Pool<AsyncPgConnection> {
+    fn claim<'a>(
+        &'a self,
+        search_id: i32,
+        auction_uuid: &'a str,
+    ) -> BoxFuture<'a, Result<bool, AHScraperError>> {
+        async move {
+            let inserted = diesel::insert_into(saved_search_hits::table)
+                .values((
+                    saved_search_hits::search_id.eq(search_id),
+                    saved_search_hits::auction_uuid.eq(auction_uuid),
+                ))
+                .on_conflict_do_nothing()
+                .execute(&mut self.get().await?)
+                .await?;
+            Ok(inserted == 1)
+        }
+        .boxed()
+    }
+
+    fn release<'a>(
+        &'a self,
+        search_id: i32,
+        auction_uuid: &'a str,
+    ) -> BoxFuture<'a, Result<(), AHScraperError>> {
+        async move {
+            diesel::delete(saved_search_hits::table.find((search_id, auction_uuid)))
+                .execute(&mut self.get().await?)
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Matches a batch of auctions against every enabled search and queues a webhook for each new
+/// hit, returning how many were queued.
+pub async fn notify_matches(
+    db: Pool<AsyncPgConnection>,
+    book: &RwLock<AuctionBook>,
+    dispatcher: &WebhookDispatcher,
+    auctions: &[AuctionModel],
+) -> Result<usize, AHScraperError> {
+    let searches = enabled_searches(&mut *db.get().await?).await?;
+    dispatch_matches(&db, &searches, book, dispatcher, auctions).await
+}
+
+/// Queues a webhook for each hit of `searches` in `auctions` that `hits` hasn't seen, releasing
+/// the hit again if its delivery gives up.
+async fn dispatch_matches<H: HitLog>(
+    hits: &H,
+    searches: &[SavedSearch],
+    book: &RwLock<AuctionBook>,
+    dispatcher: &WebhookDispatcher,
+    auctions: &[AuctionModel],
+) -> Result<usize, AHScraperError> {
+    let mut sent = 0;
+    for search in searches {
+        for auction in auctions.iter().filter(|a| search.matches(a)) {
+            if !hits.claim(search.id, &auction.uuid).await? {
+                continue;
+            }
+            let url = search.webhook_url.clone();
+            let hits = hits.clone();
+            let (search_id, uuid) = (search.id, auction.uuid.clone());
+            let gave_up = async move {
+                if let Err(e) = hits.release(search_id, &uuid).await {
+                    println!(
+                        "Failed to release hit of {} for search {}: {}",
+                        uuid, search_id, e
+                    );
+                }
+            };
+            match search.format() {
+                PayloadFormat::Json => {
+                    dispatcher.dispatch(url, SearchHit::new(search, auction), gave_up)
+                }
+                PayloadFormat::Discord => {
+                    let lowest_bin = auction
+                        .item_key
+                        .as_deref()
+                        .and_then(|key| book.read().unwrap().lowest_bin(key, Some(&auction.uuid)));
+                    let message = discord::auction_message(&search.name, auction, lowest_bin);
+                    dispatcher.dispatch(url, message, gave_up);
+                }
+            }
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_auction;
+    use crate::shutdown::Shutdown;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Clone, Default)]
+    struct MemoryHits(Arc<Mutex<HashSet<(i32, String)>>>);
+
+    impl HitLog for MemoryHits {
+        fn claim<'a>(
+            &'a self,
+            search_id: i32,
+            auction_uuid: &'a str,
+        ) -> BoxFuture<'a, Result<bool, AHScraperError>> {
+            let claimed = self
+                .0
+                .lock()
+                .unwrap()
+                .insert((search_id, auction_uuid.to_string()));
+            async move { Ok(claimed) }.boxed()
+        }
+
+        fn release<'a>(
+            &'a self,
+            search_id: i32,
+            auction_uuid: &'a str,
+        ) -> BoxFuture<'a, Result<(), AHScraperError>> {
+            self.0
+                .lock()
+                .unwrap()
+                .remove(&(search_id, auction_uuid.to_string()));
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    /// A webhook endpoint answering the first `failures` requests with a 500, returning its url
+    /// and the bodies of the requests it accepted.
+    async fn webhook(failures: usize) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let accepted = Arc::new(Mutex::new(Vec::new()));
+        let received = accepted.clone();
+        tokio::spawn(async move {
+            let mut requests = 0;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let body = loop {
+                    let mut chunk = [0; 4096];
+                    let read = socket.read(&mut chunk).await.unwrap();
+                    request.extend_from_slice(&chunk[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")?
+                                .parse()
+                                .ok()
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                };
+                requests += 1;
+                let status = if requests <= failures {
+                    "500 Internal Server Error"
+                } else {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push(serde_json::from_str(&body).unwrap());
+                    "200 OK"
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, accepted)
+    }
+
+    fn search(id: i32, url: &str, item_key: Option<&str>) -> SavedSearch {
+        SavedSearch {
+            id,
+            name: format!("search {}", id),
+            webhook_url: url.to_string(),
+            item_id: None,
+            item_key: item_key.map(str::to_string),
+            name_contains: None,
+            tier: None,
+            min_stars: None,
+            min_price: None,
+            max_price: None,
+            bin_only: true,
+            enabled: true,
+            created_at: SystemTime::now(),
+            payload_format: "json".to_string(),
+        }
+    }
+
+    async fn eventually(mut done: impl FnMut() -> bool) {
+        for _ in 0..100 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("timed out");
+    }
+
+    #[tokio::test]
+    async fn posts_each_hit_once_retrying_failed_deliveries() {
+        let (url, accepted) = webhook(1).await;
+        let searches = [search(1, &url, Some("HYPERION")), search(2, &url, None)];
+        let auctions = [
+            test_auction("a", "HYPERION", 100, true),
+            test_auction("b", "TERMINATOR", 100, true),
+            test_auction("c", "HYPERION", 100, false),
+        ];
+        let hits = MemoryHits::default();
+        let book = RwLock::new(AuctionBook::default());
+        let dispatcher = WebhookDispatcher::new(3, Duration::from_millis(10));
+        let queued = dispatch_matches(&hits, &searches, &book, &dispatcher, &auctions).await;
+        assert_eq!(queued.unwrap(), 3);
+        // the same auctions in the next batch are already claimed.
+        let queued = dispatch_matches(&hits, &searches, &book, &dispatcher, &auctions).await;
+        assert_eq!(queued.unwrap(), 0);
+        eventually(|| accepted.lock().unwrap().len() >= 3).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut posted: Vec<(i64, String)> = accepted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|hit| {
+                (
+                    hit["search_id"].as_i64().unwrap(),
+                    hit["uuid"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        posted.sort();
+        let expected = [(1, "a"), (2, "a"), (2, "b")];
+        assert_eq!(posted, expected.map(|(id, uuid)| (id, uuid.to_string())));
+        assert_eq!(hits.0.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn releases_hits_whose_delivery_gave_up() {
+        let (url, accepted) = webhook(usize::MAX).await;
+        let searches = [search(1, &url, None)];
+        let auctions = [test_auction("a", "HYPERION", 100, true)];
+        let hits = MemoryHits::default();
+        let book = RwLock::new(AuctionBook::default());
+        let dispatcher = WebhookDispatcher::new(2, Duration::from_millis(10));
+        let queued = dispatch_matches(&hits, &searches, &book, &dispatcher, &auctions).await;
+        assert_eq!(queued.unwrap(), 1);
+        eventually(|| hits.0.lock().unwrap().is_empty()).await;
+        assert!(accepted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_on_deliveries_and_releases_the_unsent() {
+        let (url, _) = webhook(usize::MAX).await;
+        let searches = [search(1, &url, None)];
+        let auctions = [test_auction("a", "HYPERION", 100, true)];
+        let hits = MemoryHits::default();
+        let book = RwLock::new(AuctionBook::default());
+        let shutdown = Shutdown::default();
+        let dispatcher =
+            WebhookDispatcher::new(5, Duration::from_secs(60)).with_shutdown(shutdown.clone());
+        let queued = dispatch_matches(&hits, &searches, &book, &dispatcher, &auctions).await;
+        assert_eq!(queued.unwrap(), 1);
+        eventually(|| !hits.0.lock().unwrap().is_empty()).await;
+        // the delivery is backing off for a minute, draining cuts that short.
+        let drained = shutdown.drain(async {}, Duration::from_secs(5)).await;
+        assert!(drained.is_some());
+        assert!(hits.0.lock().unwrap().is_empty());
+    }
+}
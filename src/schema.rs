@@ -1,7 +1,7 @@
 // @generated automatically by Diesel CLI.
 
 diesel::table! {
-    auctions (uuid) {
+    auctions (uuid, start_time) {
         uuid -> Text,
         auctioneer -> Text,
         profile_id -> Text,
@@ -21,7 +21,7 @@ diesel::table! {
         tier -> Text,
         category -> Text,
         last_updated -> Timestamp,
-        bin -> Nullable<Bool>,
+        bin -> Bool,
         reforge -> Nullable<Text>,
         upgrade_level -> Nullable<Int4>,
         hot_potato_count -> Nullable<Int4>,
@@ -45,5 +45,76 @@ diesel::table! {
         hecatomb_s_runs -> Nullable<Int4>,
         expertise_kills -> Nullable<Int4>,
         runes -> Nullable<Array<Nullable<Text>>>,
+        item_key -> Nullable<Text>,
+        buyer -> Nullable<Text>,
+        sold_price -> Nullable<Int8>,
+        highest_bid -> Nullable<Int8>,
     }
 }
+
+diesel::table! {
+    market_flags (id) {
+        id -> Int4,
+        kind -> Text,
+        auction_uuid -> Text,
+        related_auction_uuid -> Nullable<Text>,
+        item_key -> Nullable<Text>,
+        auctioneer -> Text,
+        profile_id -> Text,
+        price -> Int8,
+        reference_price -> Nullable<Int8>,
+        detected_at -> Timestamp,
+        reviewed -> Bool,
+    }
+}
+
+diesel::table! {
+    saved_search_hits (search_id, auction_uuid) {
+        search_id -> Int4,
+        auction_uuid -> Text,
+        sent_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    saved_searches (id) {
+        id -> Int4,
+        name -> Text,
+        webhook_url -> Text,
+        item_id -> Nullable<Text>,
+        item_key -> Nullable<Text>,
+        name_contains -> Nullable<Text>,
+        tier -> Nullable<Text>,
+        min_stars -> Nullable<Int4>,
+        min_price -> Nullable<Int8>,
+        max_price -> Nullable<Int8>,
+        bin_only -> Bool,
+        enabled -> Bool,
+        created_at -> Timestamp,
+        payload_format -> Text,
+    }
+}
+
+diesel::table! {
+    sellers (auctioneer, profile_id) {
+        auctioneer -> Text,
+        profile_id -> Text,
+        coop -> Nullable<Array<Nullable<Text>>>,
+        active_listings -> Int8,
+        total_listed_value -> Int8,
+        sold_count -> Int8,
+        median_markup -> Nullable<Float8>,
+        categories -> Array<Nullable<Text>>,
+        refreshed_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(saved_search_hits -> saved_searches (search_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    auctions,
+    market_flags,
+    saved_search_hits,
+    saved_searches,
+    sellers,
+);
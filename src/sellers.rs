@@ -0,0 +1,82 @@
+use crate::models::Seller;
+use crate::schema::sellers;
+use crate::AHScraperError;
+use diesel::{ExpressionMethods, PgArrayExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+/// Recomputes every seller's stats from the auctions table, returning how many sellers were
+/// written.
+pub async fn refresh_sellers(db: Pool<AsyncPgConnection>) -> Result<usize, AHScraperError> {
+    let mut conn = db.get().await?;
+    Ok(diesel::sql_query(
+        "WITH now_utc AS (SELECT now() AT TIME ZONE 'UTC' AS ts), \
+         floor AS ( \
+            SELECT item_key, min(price) AS lowest_bin FROM auctions, now_utc \
+            WHERE bin AND item_key IS NOT NULL AND end_time > now_utc.ts \
+            GROUP BY item_key \
+         ), \
+         sold AS (SELECT auction_uuid FROM item_lineage WHERE sold_price IS NOT NULL), \
+         latest_coop AS ( \
+            SELECT DISTINCT ON (auctioneer, profile_id) auctioneer, profile_id, coop \
+            FROM auctions ORDER BY auctioneer, profile_id, last_updated DESC \
+         ) \
+         INSERT INTO sellers \
+            (auctioneer, profile_id, coop, active_listings, total_listed_value, sold_count, \
+             median_markup, categories, refreshed_at) \
+         SELECT a.auctioneer, a.profile_id, c.coop, \
+                count(*) FILTER (WHERE a.end_time > now_utc.ts), \
+                coalesce(sum(a.price) FILTER (WHERE a.end_time > now_utc.ts), 0)::bigint, \
+                count(*) FILTER (WHERE a.claimed OR s.auction_uuid IS NOT NULL), \
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY a.price::float8 / f.lowest_bin - 1) \
+                    FILTER (WHERE a.bin AND a.end_time > now_utc.ts AND f.lowest_bin > 0), \
+                array_agg(DISTINCT a.category), \
+                now_utc.ts \
+         FROM auctions a \
+         CROSS JOIN now_utc \
+         JOIN latest_coop c ON c.auctioneer = a.auctioneer AND c.profile_id = a.profile_id \
+         LEFT JOIN floor f ON f.item_key = a.item_key \
+         LEFT JOIN sold s ON s.auction_uuid = a.uuid \
+         GROUP BY a.auctioneer, a.profile_id, c.coop, now_utc.ts \
+         ON CONFLICT (auctioneer, profile_id) DO UPDATE SET \
+            coop = excluded.coop, \
+            active_listings = excluded.active_listings, \
+            total_listed_value = excluded.total_listed_value, \
+            sold_count = excluded.sold_count, \
+            median_markup = excluded.median_markup, \
+            categories = excluded.categories, \
+            refreshed_at = excluded.refreshed_at",
+    )
+    .execute(&mut conn)
+    .await?)
+}
+
+/// Biggest sellers by value currently listed, optionally only those trading in `category`.
+pub async fn top_sellers(
+    conn: &mut AsyncPgConnection,
+    category: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Seller>, AHScraperError> {
+    let mut query = sellers::table.into_boxed();
+    if let Some(category) = category {
+        query = query.filter(sellers::categories.contains(vec![category]));
+    }
+    Ok(query
+        .order(sellers::total_listed_value.desc())
+        .limit(limit)
+        .select(Seller::as_select())
+        .load(conn)
+        .await?)
+}
+
+/// Stats for every profile a player sells from.
+pub async fn seller(
+    conn: &mut AsyncPgConnection,
+    auctioneer: &str,
+) -> Result<Vec<Seller>, AHScraperError> {
+    Ok(sellers::table
+        .filter(sellers::auctioneer.eq(auctioneer))
+        .select(Seller::as_select())
+        .load(conn)
+        .await?)
+}
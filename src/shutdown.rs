@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// Shared by everything that has to stop cleanly: cancelled on Ctrl-C or SIGTERM, and tracking
+/// the background tasks that should be allowed to finish their writes before the process exits.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: TaskTracker,
+    stats: Arc<RunStats>,
+}
+
+/// Counters reported in the summary printed on exit.
+#[derive(Default)]
+pub struct RunStats {
+    pub quick_scans: AtomicU64,
+    pub full_scans: AtomicU64,
+    pub auctions_written: AtomicU64,
+    /// Auctions a quick scan skipped because they hadn't changed since they were written.
+    pub auctions_unchanged: AtomicU64,
+}
+
+impl Shutdown {
+    /// Cancels on the first Ctrl-C or SIGTERM, a second one exits right away.
+    pub fn listen() -> Self {
+        let shutdown = Shutdown::default();
+        let token = shutdown.token.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            println!("Shutdown requested, finishing in-flight work (signal again to force)");
+            token.cancel();
+            wait_for_signal().await;
+            println!("Forced shutdown");
+            std::process::exit(130);
+        });
+        shutdown
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Stops everything as if shutdown was requested, for when the work runs out on its own.
+    pub fn stop(&self) {
+        self.token.cancel();
+    }
+
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Sleeps for `duration`, returning false if shutdown was requested first.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => true,
+            _ = self.token.cancelled() => false,
+        }
+    }
+
+    /// Spawns a task that shutdown waits on.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+
+    /// Runs `main` to completion along with every spawned task, unless shutdown is requested and
+    /// they are still going `timeout` later. Returns None when they were cut off.
+    pub async fn drain<F: Future>(&self, main: F, timeout: Duration) -> Option<F::Output> {
+        let work = async {
+            let output = main.await;
+            // background loops stop once the main work is done too.
+            self.token.cancel();
+            self.tasks.close();
+            self.tasks.wait().await;
+            output
+        };
+        let deadline = async {
+            self.token.cancelled().await;
+            tokio::time::sleep(timeout).await;
+        };
+        tokio::select! {
+            output = work => Some(output),
+            _ = deadline => None,
+        }
+    }
+
+    pub fn print_summary(&self, started: Instant, drained: bool) {
+        println!(
+            "Stopped after {:.0?}: {} quick scans, {} full scans, {} auctions written ({} unchanged skipped){}",
+            started.elapsed(),
+            self.stats.quick_scans.load(Ordering::Relaxed),
+            self.stats.full_scans.load(Ordering::Relaxed),
+            self.stats.auctions_written.load(Ordering::Relaxed),
+            self.stats.auctions_unchanged.load(Ordering::Relaxed),
+            if drained {
+                ""
+            } else {
+                ", timed out waiting on in-flight writes"
+            }
+        );
+    }
+}
+
+impl RunStats {
+    pub fn record_quick_scan(&self) {
+        self.quick_scans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_full_scan(&self) {
+        self.full_scans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_written(&self, auctions: usize) {
+        self.auctions_written
+            .fetch_add(auctions as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_unchanged(&self, auctions: usize) {
+        self.auctions_unchanged
+            .fetch_add(auctions as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,56 @@
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::ended::EndedAuction;
+use crate::models::AuctionSummary;
+use crate::upsert::RefreshColumns;
+use crate::AHScraperError;
+use futures::future::BoxFuture;
+use std::time::SystemTime;
+
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Where scraped auctions are kept, so the scraper can run against Postgres or, with the sqlite
+/// feature, a local file.
+pub trait AuctionStore: Send + Sync {
+    /// Inserts new auctions and overwrites the `refresh` columns of ones already stored.
+    fn upsert<'a>(
+        &'a self,
+        auctions: &'a [Auction],
+        refresh: &'a RefreshColumns,
+    ) -> BoxFuture<'a, Result<(), AHScraperError>>;
+
+    /// Ends stored auctions that closed before their end time, bought or cancelled, at `at`.
+    /// Returns how many were still open.
+    fn mark_closed<'a>(
+        &'a self,
+        uuids: &'a [String],
+        at: SystemTime,
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>>;
+
+    /// Stores who bought each auction from the ended endpoint and for how much, marking it
+    /// claimed and ended when it sold. Returns how many were stored, unknown auctions are skipped.
+    fn record_sales<'a>(
+        &'a self,
+        sales: &'a [EndedAuction],
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>>;
+
+    /// Open auctions of an item key, cheapest first.
+    fn by_item_key<'a>(
+        &'a self,
+        item_key: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<AuctionSummary>, AHScraperError>>;
+
+    /// Cheapest open BIN listing of an item key.
+    fn lowest_bin<'a>(
+        &'a self,
+        item_key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, AHScraperError>>;
+}
+
+/// The file a `sqlite://path` or `sqlite:path` database URL points at.
+pub fn sqlite_path(url: &str) -> Option<&str> {
+    url.strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+}
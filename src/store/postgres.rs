@@ -0,0 +1,247 @@
+use crate::bulk;
+use crate::config::{Config, IngestMode};
+use crate::flips;
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::ended::EndedAuction;
+use crate::models::{Auction as AuctionModel, AuctionSummary};
+use crate::partitions;
+use crate::schema::auctions;
+use crate::store::AuctionStore;
+use crate::upsert::{ExcludedColumns, RefreshColumns};
+use crate::AHScraperError;
+use diesel::sql_types::{Array, BigInt, Text, Timestamp};
+use diesel::{ConnectionError, ConnectionResult, ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::pooled_connection::bb8::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt};
+use native_tls::TlsConnector;
+use postgres_native_tls::MakeTlsConnector;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use tokio::task;
+use tokio_postgres::config::SslMode;
+use tokio_postgres::{Client, NoTls};
+
+/// Connects a raw client to `database_url`, over TLS when its `sslmode` is `require` and in the
+/// clear otherwise. Pooled connections and `COPY` ingest both connect through here.
+pub async fn connect(database_url: &str) -> Result<Client, AHScraperError> {
+    let config: tokio_postgres::Config = database_url.parse()?;
+    if config.get_ssl_mode() == SslMode::Require {
+        let tls = MakeTlsConnector::new(TlsConnector::new()?);
+        let (client, connection) = config.connect(tls).await?;
+        tokio::spawn(drive(connection));
+        Ok(client)
+    } else {
+        let (client, connection) = config.connect(NoTls).await?;
+        tokio::spawn(drive(connection));
+        Ok(client)
+    }
+}
+
+/// Runs a client's connection until it closes, reporting why if it broke.
+async fn drive(connection: impl Future<Output = Result<(), tokio_postgres::Error>>) {
+    if let Err(e) = connection.await {
+        println!("Postgres connection closed: {}", e);
+    }
+}
+
+/// Sets up a pooled connection through [`connect`].
+pub fn establish(database_url: &str) -> BoxFuture<'_, ConnectionResult<AsyncPgConnection>> {
+    async move {
+        let client = connect(database_url)
+            .await
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        AsyncPgConnection::try_from(client).await
+    }
+    .boxed()
+}
+
+/// The auctions table in Postgres, written with chunked upserts or `COPY` as configured.
+#[derive(Clone)]
+pub struct PostgresStore {
+    db: Pool<AsyncPgConnection>,
+    database_url: String,
+    /// The connection `COPY` ingest writes through, opened on first use and again if it drops.
+    copy_client: Arc<Mutex<Option<Client>>>,
+    ingest: IngestMode,
+    chunk_size: usize,
+    retention_months: Option<u32>,
+}
+
+impl PostgresStore {
+    pub fn new(db: Pool<AsyncPgConnection>, config: &Config) -> Self {
+        PostgresStore {
+            db,
+            database_url: config.database_url().to_string(),
+            copy_client: Arc::new(Mutex::new(None)),
+            ingest: config.scrape.ingest,
+            chunk_size: config.scrape.chunk_size,
+            retention_months: config.database.retention_months,
+        }
+    }
+
+    pub fn with_ingest(mut self, ingest: IngestMode) -> Self {
+        self.ingest = ingest;
+        self
+    }
+}
+
+impl AuctionStore for PostgresStore {
+    fn upsert<'a>(
+        &'a self,
+        auctions: &'a [Auction],
+        refresh: &'a RefreshColumns,
+    ) -> BoxFuture<'a, Result<(), AHScraperError>> {
+        async move {
+            let now = SystemTime::now();
+            let expired = |auction: &Auction| {
+                partitions::past_retention(auction.start, auction.end, self.retention_months, now)
+            };
+            let kept: Vec<Auction>;
+            let auctions = if auctions.iter().any(expired) {
+                kept = auctions.iter().filter(|a| !expired(a)).cloned().collect();
+                println!(
+                    "Skipped {} auctions from months past the retention",
+                    auctions.len() - kept.len()
+                );
+                &kept
+            } else {
+                auctions
+            };
+            match self.ingest {
+                IngestMode::Chunked => Ok(process_auctions_in_parallel(
+                    self.db.clone(),
+                    auctions,
+                    self.chunk_size,
+                    refresh.columns(),
+                )
+                .await?),
+                IngestMode::Copy => {
+                    let mut slot = self.copy_client.lock().await;
+                    let client = match slot.take() {
+                        Some(open) if !open.is_closed() => slot.insert(open),
+                        _ => slot.insert(connect(&self.database_url).await?),
+                    };
+                    bulk::copy_auctions(client, auctions, &refresh.columns()).await?;
+                    Ok(())
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn mark_closed<'a>(
+        &'a self,
+        uuids: &'a [String],
+        at: SystemTime,
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>> {
+        async move {
+            let mut conn = self.db.get().await?;
+            Ok(diesel::update(auctions::table)
+                .filter(auctions::uuid.eq_any(uuids))
+                .filter(auctions::end_time.gt(at))
+                .set(auctions::end_time.eq(at))
+                .execute(&mut conn)
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn record_sales<'a>(
+        &'a self,
+        sales: &'a [EndedAuction],
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>> {
+        async move {
+            if sales.is_empty() {
+                return Ok(0);
+            }
+            let mut conn = self.db.get().await?;
+            Ok(diesel::sql_query(
+                "UPDATE auctions a SET claimed = true, buyer = s.buyer, sold_price = s.price, \
+                    end_time = least(a.end_time, s.sold_at) \
+                 FROM unnest($1, $2, $3, $4) AS s(uuid, buyer, price, sold_at) \
+                 WHERE a.uuid = s.uuid",
+            )
+            .bind::<Array<Text>, _>(sales.iter().map(|s| &s.auction_id).collect::<Vec<_>>())
+            .bind::<Array<Text>, _>(sales.iter().map(|s| &s.buyer).collect::<Vec<_>>())
+            .bind::<Array<BigInt>, _>(sales.iter().map(|s| s.price).collect::<Vec<_>>())
+            .bind::<Array<Timestamp>, _>(sales.iter().map(|s| s.timestamp).collect::<Vec<_>>())
+            .execute(&mut conn)
+            .await?)
+        }
+        .boxed()
+    }
+
+    fn by_item_key<'a>(
+        &'a self,
+        item_key: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<AuctionSummary>, AHScraperError>> {
+        async move {
+            let mut conn = self.db.get().await?;
+            Ok(auctions::table
+                .filter(auctions::item_key.eq(item_key))
+                .filter(auctions::end_time.gt(SystemTime::now()))
+                .order(auctions::price.asc())
+                .limit(limit)
+                .select(AuctionSummary::as_select())
+                .load(&mut conn)
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn lowest_bin<'a>(
+        &'a self,
+        item_key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, AHScraperError>> {
+        async move { flips::lowest_bin(&mut *self.db.get().await?, item_key, None).await }.boxed()
+    }
+}
+
+async fn upsert_auctions(
+    db: Pool<AsyncPgConnection>,
+    auctions_values: Vec<Auction>,
+    refresh: Vec<&'static str>,
+) -> Result<(), AHScraperError> {
+    let dbauctions: Vec<AuctionModel> = auctions_values
+        .into_iter()
+        .map(AuctionModel::from)
+        .collect::<Vec<_>>();
+    let mut conn = db.get().await?;
+    diesel::insert_into(auctions::table)
+        .values(&dbauctions)
+        .on_conflict((auctions::uuid, auctions::start_time))
+        .do_update()
+        .set(ExcludedColumns(auctions::table, refresh))
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+async fn process_auctions_in_parallel(
+    db: Pool<AsyncPgConnection>,
+    auctions: &[Auction],
+    chunk_size: usize,
+    refresh: Vec<&'static str>,
+) -> Result<(), Box<AHScraperError>> {
+    let mut tasks = Vec::new();
+
+    for chunk in auctions.chunks(chunk_size) {
+        let chunk = chunk.to_owned().to_vec(); // Clone the chunk data
+                                               // Clone the collection handle
+        let db_clone = db.clone();
+        let refresh = refresh.clone();
+        let task = task::spawn(async move { upsert_auctions(db_clone, chunk, refresh).await });
+
+        tasks.push(task);
+    }
+
+    for task in tasks {
+        task.await??;
+    }
+    println!("Finished processing all auctions in parallel");
+    Ok(())
+}
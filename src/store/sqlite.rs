@@ -0,0 +1,436 @@
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::ended::EndedAuction;
+use crate::models::{Auction as AuctionModel, AuctionSummary};
+use crate::store::AuctionStore;
+use crate::upsert::{ExcludedColumns, RefreshColumns};
+use crate::AHScraperError;
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::{BigInt, Text};
+use diesel::sqlite::SqliteConnection;
+use diesel::{
+    Connection, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// SQLite has no arrays or timestamps, so lists are stored as JSON and times as milliseconds
+/// since the epoch. Otherwise the same columns as the Postgres table.
+mod schema {
+    diesel::table! {
+        auctions (uuid) {
+            uuid -> Text,
+            auctioneer -> Text,
+            profile_id -> Text,
+            coop -> Nullable<Text>,
+            start_time -> BigInt,
+            end_time -> BigInt,
+            item_name -> Text,
+            item_uuid -> Nullable<Text>,
+            item_lore -> Nullable<Text>,
+            item_id -> Nullable<Text>,
+            item_count -> Nullable<Integer>,
+            item_damage -> Nullable<Integer>,
+            enchantments -> Nullable<Text>,
+            unbreakable -> Nullable<Bool>,
+            price -> BigInt,
+            claimed -> Nullable<Bool>,
+            tier -> Text,
+            category -> Text,
+            last_updated -> BigInt,
+            bin -> Bool,
+            reforge -> Nullable<Text>,
+            upgrade_level -> Nullable<Integer>,
+            hot_potato_count -> Nullable<Integer>,
+            recomb -> Nullable<Bool>,
+            unlocked_gem_slots -> Nullable<Text>,
+            slotted_gems -> Nullable<Text>,
+            pet_active -> Nullable<Bool>,
+            pet_type -> Nullable<Text>,
+            pet_held_item -> Nullable<Text>,
+            pet_exp -> Nullable<Integer>,
+            pet_candy_used -> Nullable<Integer>,
+            dungeon_item_level -> Nullable<Integer>,
+            red_armor_coloring -> Nullable<Integer>,
+            green_armor_coloring -> Nullable<Integer>,
+            blue_armor_coloring -> Nullable<Integer>,
+            anvil_uses -> Nullable<Integer>,
+            pelts_earned -> Nullable<Integer>,
+            champion_combat_xp -> Nullable<Integer>,
+            farmed_cultivating -> Nullable<Integer>,
+            compact_blocks -> Nullable<Integer>,
+            hecatomb_s_runs -> Nullable<Integer>,
+            expertise_kills -> Nullable<Integer>,
+            runes -> Nullable<Text>,
+            item_key -> Nullable<Text>,
+            buyer -> Nullable<Text>,
+            sold_price -> Nullable<BigInt>,
+            highest_bid -> Nullable<BigInt>,
+        }
+    }
+}
+
+use schema::auctions;
+
+const CREATE_TABLES: &str = "\
+CREATE TABLE IF NOT EXISTS auctions (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    auctioneer TEXT NOT NULL,
+    profile_id TEXT NOT NULL,
+    coop TEXT,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL,
+    item_name TEXT NOT NULL,
+    item_uuid TEXT,
+    item_lore TEXT,
+    item_id TEXT,
+    item_count INTEGER,
+    item_damage INTEGER,
+    enchantments TEXT,
+    unbreakable BOOLEAN,
+    price BIGINT NOT NULL,
+    claimed BOOLEAN,
+    tier TEXT NOT NULL,
+    category TEXT NOT NULL,
+    last_updated BIGINT NOT NULL,
+    bin BOOLEAN NOT NULL,
+    reforge TEXT,
+    upgrade_level INTEGER,
+    hot_potato_count INTEGER,
+    recomb BOOLEAN,
+    unlocked_gem_slots TEXT,
+    slotted_gems TEXT,
+    pet_active BOOLEAN,
+    pet_type TEXT,
+    pet_held_item TEXT,
+    pet_exp INTEGER,
+    pet_candy_used INTEGER,
+    dungeon_item_level INTEGER,
+    red_armor_coloring INTEGER,
+    green_armor_coloring INTEGER,
+    blue_armor_coloring INTEGER,
+    anvil_uses INTEGER,
+    pelts_earned INTEGER,
+    champion_combat_xp INTEGER,
+    farmed_cultivating INTEGER,
+    compact_blocks INTEGER,
+    hecatomb_s_runs INTEGER,
+    expertise_kills INTEGER,
+    runes TEXT,
+    item_key TEXT,
+    buyer TEXT,
+    sold_price BIGINT,
+    highest_bid BIGINT
+);
+CREATE INDEX IF NOT EXISTS auctions_item_key_price ON auctions (item_key, price);";
+
+/// SQLite limits how many values one statement can bind.
+const MAX_BINDS: usize = 1000;
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = auctions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct SqliteAuction {
+    uuid: String,
+    auctioneer: String,
+    profile_id: String,
+    coop: Option<String>,
+    start_time: i64,
+    end_time: i64,
+    item_name: String,
+    item_uuid: Option<String>,
+    item_lore: Option<String>,
+    item_id: Option<String>,
+    item_count: Option<i32>,
+    item_damage: Option<i32>,
+    enchantments: Option<String>,
+    unbreakable: Option<bool>,
+    price: i64,
+    claimed: Option<bool>,
+    tier: String,
+    category: String,
+    last_updated: i64,
+    bin: bool,
+    reforge: Option<String>,
+    upgrade_level: Option<i32>,
+    hot_potato_count: Option<i32>,
+    recomb: Option<bool>,
+    unlocked_gem_slots: Option<String>,
+    slotted_gems: Option<String>,
+    pet_active: Option<bool>,
+    pet_type: Option<String>,
+    pet_held_item: Option<String>,
+    pet_exp: Option<i32>,
+    pet_candy_used: Option<i32>,
+    dungeon_item_level: Option<i32>,
+    red_armor_coloring: Option<i32>,
+    green_armor_coloring: Option<i32>,
+    blue_armor_coloring: Option<i32>,
+    anvil_uses: Option<i32>,
+    pelts_earned: Option<i32>,
+    champion_combat_xp: Option<i32>,
+    farmed_cultivating: Option<i32>,
+    compact_blocks: Option<i32>,
+    hecatomb_s_runs: Option<i32>,
+    expertise_kills: Option<i32>,
+    runes: Option<String>,
+    item_key: Option<String>,
+    highest_bid: Option<i64>,
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn to_json(list: Option<Vec<String>>) -> Option<String> {
+    list.map(|list| serde_json::Value::from(list).to_string())
+}
+
+fn from_json(list: Option<String>) -> Option<Vec<Option<String>>> {
+    list.and_then(|list| serde_json::from_str(&list).ok())
+}
+
+impl From<AuctionModel> for SqliteAuction {
+    fn from(a: AuctionModel) -> Self {
+        SqliteAuction {
+            uuid: a.uuid,
+            auctioneer: a.auctioneer,
+            profile_id: a.profile_id,
+            coop: to_json(a.coop),
+            start_time: millis(a.start_time),
+            end_time: millis(a.end_time),
+            item_name: a.item_name,
+            item_uuid: a.item_uuid,
+            item_lore: a.item_lore,
+            item_id: a.item_id,
+            item_count: a.item_count,
+            item_damage: a.item_damage,
+            enchantments: to_json(a.enchantments),
+            unbreakable: a.unbreakable,
+            price: a.price,
+            claimed: a.claimed,
+            tier: a.tier,
+            category: a.category,
+            last_updated: millis(a.last_updated),
+            bin: a.bin,
+            reforge: a.reforge,
+            upgrade_level: a.upgrade_level,
+            hot_potato_count: a.hot_potato_count,
+            recomb: a.recomb,
+            unlocked_gem_slots: to_json(a.unlocked_gem_slots),
+            slotted_gems: to_json(a.slotted_gems),
+            pet_active: a.pet_active,
+            pet_type: a.pet_type,
+            pet_held_item: a.pet_held_item,
+            pet_exp: a.pet_exp,
+            pet_candy_used: a.pet_candy_used,
+            dungeon_item_level: a.dungeon_item_level,
+            red_armor_coloring: a.red_armor_coloring,
+            green_armor_coloring: a.green_armor_coloring,
+            blue_armor_coloring: a.blue_armor_coloring,
+            anvil_uses: a.anvil_uses,
+            pelts_earned: a.pelts_earned,
+            champion_combat_xp: a.champion_combat_xp,
+            farmed_cultivating: a.farmed_cultivating,
+            compact_blocks: a.compact_blocks,
+            hecatomb_s_runs: a.hecatomb_s_runs,
+            expertise_kills: a.expertise_kills,
+            runes: to_json(a.runes),
+            item_key: a.item_key,
+            highest_bid: a.highest_bid,
+        }
+    }
+}
+
+impl From<SqliteAuction> for AuctionSummary {
+    fn from(a: SqliteAuction) -> Self {
+        AuctionSummary {
+            uuid: a.uuid,
+            auctioneer: a.auctioneer,
+            profile_id: a.profile_id,
+            start_time: from_millis(a.start_time),
+            end_time: from_millis(a.end_time),
+            last_updated: from_millis(a.last_updated),
+            item_name: a.item_name,
+            item_id: a.item_id,
+            item_key: a.item_key,
+            item_uuid: a.item_uuid,
+            item_lore: a.item_lore,
+            tier: a.tier,
+            category: a.category,
+            price: a.price,
+            highest_bid: a.highest_bid,
+            bin: a.bin,
+            claimed: a.claimed,
+            reforge: a.reforge,
+            upgrade_level: a.upgrade_level,
+            hot_potato_count: a.hot_potato_count,
+            recomb: a.recomb,
+            enchantments: from_json(a.enchantments),
+            slotted_gems: from_json(a.slotted_gems),
+            runes: from_json(a.runes),
+            pet_type: a.pet_type,
+            pet_exp: a.pet_exp,
+        }
+    }
+}
+
+/// Auctions in a local SQLite file, for running the scraper without a Postgres server.
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<SqliteConnection>>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path` and creates the tables it's missing.
+    pub async fn open(path: &str) -> Result<Self, AHScraperError> {
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = SqliteConnection::establish(&path)?;
+            conn.batch_execute(CREATE_TABLES)?;
+            Ok(SqliteStore {
+                conn: Arc::new(Mutex::new(conn)),
+            })
+        })
+        .await?
+    }
+
+    /// Runs `query` on the connection off the async runtime, SQLite calls block.
+    async fn with_conn<T, F>(&self, query: F) -> Result<T, AHScraperError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SqliteConnection) -> Result<T, AHScraperError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || query(&mut conn.lock().unwrap())).await?
+    }
+}
+
+impl AuctionStore for SqliteStore {
+    fn upsert<'a>(
+        &'a self,
+        auctions: &'a [Auction],
+        refresh: &'a RefreshColumns,
+    ) -> BoxFuture<'a, Result<(), AHScraperError>> {
+        let rows: Vec<SqliteAuction> = auctions
+            .iter()
+            .cloned()
+            .map(|auction| AuctionModel::from(auction).into())
+            .collect();
+        let refresh = refresh.columns();
+        self.with_conn(move |conn| {
+            conn.transaction(|conn| {
+                for row in &rows {
+                    diesel::insert_into(auctions::table)
+                        .values(row)
+                        .on_conflict(auctions::uuid)
+                        .do_update()
+                        .set(ExcludedColumns(auctions::table, refresh.clone()))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+        })
+        .boxed()
+    }
+
+    fn mark_closed<'a>(
+        &'a self,
+        uuids: &'a [String],
+        at: SystemTime,
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>> {
+        let uuids = uuids.to_vec();
+        let at = millis(at);
+        self.with_conn(move |conn| {
+            let mut closed = 0;
+            for chunk in uuids.chunks(MAX_BINDS) {
+                closed += diesel::update(auctions::table)
+                    .filter(auctions::uuid.eq_any(chunk))
+                    .filter(auctions::end_time.gt(at))
+                    .set(auctions::end_time.eq(at))
+                    .execute(conn)?;
+            }
+            Ok(closed)
+        })
+        .boxed()
+    }
+
+    fn record_sales<'a>(
+        &'a self,
+        sales: &'a [EndedAuction],
+    ) -> BoxFuture<'a, Result<usize, AHScraperError>> {
+        let sales: Vec<(String, String, i64, i64)> = sales
+            .iter()
+            .map(|s| {
+                (
+                    s.auction_id.clone(),
+                    s.buyer.clone(),
+                    s.price,
+                    millis(s.timestamp),
+                )
+            })
+            .collect();
+        self.with_conn(move |conn| {
+            conn.transaction(|conn| {
+                let mut recorded = 0;
+                for (uuid, buyer, price, sold_at) in sales {
+                    recorded += diesel::sql_query(
+                        "UPDATE auctions SET claimed = true, buyer = ?, sold_price = ?, \
+                            end_time = min(end_time, ?) WHERE uuid = ?",
+                    )
+                    .bind::<Text, _>(buyer)
+                    .bind::<BigInt, _>(price)
+                    .bind::<BigInt, _>(sold_at)
+                    .bind::<Text, _>(uuid)
+                    .execute(conn)?;
+                }
+                Ok(recorded)
+            })
+        })
+        .boxed()
+    }
+
+    fn by_item_key<'a>(
+        &'a self,
+        item_key: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<AuctionSummary>, AHScraperError>> {
+        let item_key = item_key.to_string();
+        self.with_conn(move |conn| {
+            let rows: Vec<SqliteAuction> = auctions::table
+                .filter(auctions::item_key.eq(item_key))
+                .filter(auctions::end_time.gt(millis(SystemTime::now())))
+                .order(auctions::price.asc())
+                .limit(limit)
+                .select(SqliteAuction::as_select())
+                .load(conn)?;
+            Ok(rows.into_iter().map(AuctionSummary::from).collect())
+        })
+        .boxed()
+    }
+
+    fn lowest_bin<'a>(
+        &'a self,
+        item_key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, AHScraperError>> {
+        let item_key = item_key.to_string();
+        self.with_conn(move |conn| {
+            Ok(auctions::table
+                .filter(auctions::item_key.eq(item_key))
+                .filter(auctions::bin.eq(true))
+                .filter(auctions::end_time.gt(millis(SystemTime::now())))
+                .order(auctions::price.asc())
+                .select(auctions::price)
+                .first(conn)
+                .optional()?)
+        })
+        .boxed()
+    }
+}
@@ -0,0 +1,195 @@
+use crate::shutdown::Shutdown;
+use crate::AHScraperError;
+use futures::FutureExt;
+use serde::Serialize;
+use serde_with::{serde_as, DurationMilliSeconds, TimestampMilliSeconds};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// How a supervised job has been doing.
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub runs: u64,
+    pub failures: u64,
+    /// Failures since the last success, drives the backoff.
+    pub consecutive_failures: u32,
+    #[serde_as(as = "Option<TimestampMilliSeconds<i64>>")]
+    pub last_success: Option<SystemTime>,
+    #[serde_as(as = "Option<TimestampMilliSeconds<i64>>")]
+    pub last_failure: Option<SystemTime>,
+    pub last_error: Option<String>,
+    /// How long the last run took, successful or not.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    pub last_duration: Option<Duration>,
+}
+
+/// Runs the scraper's jobs so that a failing one is retried with exponential backoff instead of
+/// taking the process down, keeping track of how each is doing.
+#[derive(Clone)]
+pub struct Supervisor {
+    jobs: Arc<Mutex<BTreeMap<&'static str, JobStatus>>>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new(Duration::from_secs(1), Duration::from_secs(5 * 60))
+    }
+}
+
+impl Supervisor {
+    pub fn new(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Supervisor {
+            jobs: Arc::default(),
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    pub fn statuses(&self) -> BTreeMap<&'static str, JobStatus> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    /// Runs `job` once, recording how it went. Panics count as failures.
+    pub async fn run_once<T, F>(&self, name: &'static str, job: F) -> Result<T, AHScraperError>
+    where
+        F: Future<Output = Result<T, AHScraperError>>,
+    {
+        let started = Instant::now();
+        let result = match AssertUnwindSafe(job).catch_unwind().await {
+            Ok(result) => result,
+            Err(_) => Err(AHScraperError::Panic(name.to_string())),
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        let status = jobs.entry(name).or_default();
+        status.runs += 1;
+        status.last_duration = Some(started.elapsed());
+        match &result {
+            Ok(_) => {
+                status.consecutive_failures = 0;
+                status.last_success = Some(SystemTime::now());
+            }
+            Err(e) => {
+                status.failures += 1;
+                status.consecutive_failures += 1;
+                status.last_failure = Some(SystemTime::now());
+                status.last_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    /// How long to wait before retrying `name`, doubling with every failure in a row.
+    fn backoff(&self, name: &'static str) -> Duration {
+        let failures = self
+            .jobs
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or(0, |status| status.consecutive_failures);
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Runs `job` until shutdown, pausing `every` after each success and backing off after each
+    /// failure.
+    pub async fn run_periodic<F, Fut>(
+        &self,
+        name: &'static str,
+        every: Duration,
+        shutdown: &Shutdown,
+        mut job: F,
+    ) where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), AHScraperError>>,
+    {
+        while !shutdown.is_shutting_down() {
+            let pause = match self.run_once(name, job()).await {
+                Ok(()) => every,
+                Err(e) => {
+                    let backoff = self.backoff(name);
+                    println!("{} failed, retrying in {:.0?}: {}", name, backoff, e);
+                    backoff
+                }
+            };
+            if !shutdown.sleep(pause).await {
+                break;
+            }
+        }
+    }
+
+    /// Starts `job` every `every` of wall-clock time until shutdown, the first time one interval
+    /// from now. Runs never overlap: one that overruns skips the starts it missed, and a failed one
+    /// is retried with backoff before the schedule carries on.
+    pub async fn run_scheduled<F, Fut>(
+        &self,
+        name: &'static str,
+        every: Duration,
+        shutdown: &Shutdown,
+        mut job: F,
+    ) where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), AHScraperError>>,
+    {
+        let mut schedule = tokio::time::interval_at(Instant::now() + every, every);
+        schedule.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = schedule.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            if self.retry(name, shutdown, &mut job).await.is_none() {
+                return;
+            }
+        }
+    }
+
+    /// Retries `job` with backoff until it succeeds, or returns None on shutdown.
+    pub async fn retry<T, F, Fut>(
+        &self,
+        name: &'static str,
+        shutdown: &Shutdown,
+        mut job: F,
+    ) -> Option<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AHScraperError>>,
+    {
+        while !shutdown.is_shutting_down() {
+            match self.run_once(name, job()).await {
+                Ok(value) => return Some(value),
+                Err(e) => {
+                    let backoff = self.backoff(name);
+                    println!("{} failed, retrying in {:.0?}: {}", name, backoff, e);
+                    if !shutdown.sleep(backoff).await {
+                        break;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn print_summary(&self) {
+        for (name, status) in self.statuses() {
+            println!(
+                "  {}: {} runs, {} failed{}",
+                name,
+                status.runs,
+                status.failures,
+                status
+                    .last_error
+                    .map(|e| format!(", last error: {}", e))
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
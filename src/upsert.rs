@@ -0,0 +1,182 @@
+use crate::AHScraperError;
+use diesel::backend::Backend;
+use diesel::query_builder::{AsChangeset, AstPass, QueryFragment, QueryId};
+use diesel::{QueryResult, Table};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// A set of `auctions` columns an upsert can overwrite when the auction is already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnGroup {
+    /// What changes while an auction is listed: the highest bid, end time and whether it was
+    /// claimed.
+    State,
+    /// Who listed it and how, fixed once listed. `start_time` is left out, it's part of the key.
+    Listing,
+    /// Everything parsed from the item, changes when the parser does.
+    Item,
+}
+
+impl ColumnGroup {
+    pub const ALL: [ColumnGroup; 3] = [ColumnGroup::State, ColumnGroup::Listing, ColumnGroup::Item];
+
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            ColumnGroup::State => &[
+                "last_updated",
+                "end_time",
+                "claimed",
+                "price",
+                "highest_bid",
+            ],
+            ColumnGroup::Listing => &["auctioneer", "profile_id", "coop", "category", "bin"],
+            ColumnGroup::Item => &[
+                "item_name",
+                "item_lore",
+                "tier",
+                "item_uuid",
+                "item_id",
+                "item_count",
+                "item_damage",
+                "enchantments",
+                "unbreakable",
+                "reforge",
+                "upgrade_level",
+                "hot_potato_count",
+                "recomb",
+                "unlocked_gem_slots",
+                "slotted_gems",
+                "pet_active",
+                "pet_type",
+                "pet_held_item",
+                "pet_exp",
+                "pet_candy_used",
+                "dungeon_item_level",
+                "red_armor_coloring",
+                "green_armor_coloring",
+                "blue_armor_coloring",
+                "anvil_uses",
+                "pelts_earned",
+                "champion_combat_xp",
+                "farmed_cultivating",
+                "compact_blocks",
+                "hecatomb_s_runs",
+                "expertise_kills",
+                "runes",
+                "item_key",
+            ],
+        }
+    }
+}
+
+impl FromStr for ColumnGroup {
+    type Err = AHScraperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "state" => Ok(ColumnGroup::State),
+            "listing" => Ok(ColumnGroup::Listing),
+            "item" => Ok(ColumnGroup::Item),
+            _ => Err(AHScraperError::InvalidArgument(format!(
+                "unknown column group {} (expected state, listing or item)",
+                s
+            ))),
+        }
+    }
+}
+
+/// The column groups an upsert refreshes, a comma separated list on the command line and in the
+/// environment.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct RefreshColumns(pub Vec<ColumnGroup>);
+
+impl RefreshColumns {
+    /// Every column, for full rescans and reprocessing.
+    pub fn full() -> Self {
+        RefreshColumns(ColumnGroup::ALL.to_vec())
+    }
+
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns = Vec::new();
+        for group in &self.0 {
+            for column in group.columns() {
+                if !columns.contains(column) {
+                    columns.push(*column);
+                }
+            }
+        }
+        columns
+    }
+}
+
+impl Default for RefreshColumns {
+    fn default() -> Self {
+        RefreshColumns(vec![ColumnGroup::State, ColumnGroup::Item])
+    }
+}
+
+impl FromStr for RefreshColumns {
+    type Err = AHScraperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(RefreshColumns)
+    }
+}
+
+/// `column = excluded.column` for each column of `T`, the `ON CONFLICT DO UPDATE` set diesel
+/// can't build from a list known only at runtime. Nothing to set turns into `DO NOTHING`.
+pub struct ExcludedColumns<T>(pub T, pub Vec<&'static str>);
+
+impl<T> QueryId for ExcludedColumns<T> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<T, DB: Backend> QueryFragment<DB> for ExcludedColumns<T> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        for (i, column) in self.1.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_identifier(column)?;
+            out.push_sql(" = excluded.");
+            out.push_identifier(column)?;
+        }
+        Ok(())
+    }
+
+    fn is_noop(&self, _: &DB) -> QueryResult<bool> {
+        Ok(self.1.is_empty())
+    }
+}
+
+impl<T: Table> AsChangeset for ExcludedColumns<T> {
+    type Target = T;
+    type Changeset = Self;
+
+    fn as_changeset(self) -> Self::Changeset {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_columns_parse_a_comma_separated_list() {
+        let refresh: RefreshColumns = " state, item ,".parse().unwrap();
+        assert_eq!(refresh, RefreshColumns::default());
+        assert!("state,price".parse::<RefreshColumns>().is_err());
+        assert_eq!("".parse::<RefreshColumns>().unwrap().columns().len(), 0);
+        let columns = RefreshColumns::full().columns();
+        assert!(columns.contains(&"price") && columns.contains(&"item_key"));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::book::AuctionBook;
+use crate::hypixel_api::auction::Auction;
+use crate::hypixel_api::bazaar::Bazaar;
+use crate::hypixel_api::item::{ExtraAttributes, Gem};
+use crate::models;
+
+/// Something applied to an item that costs coins to reproduce, named by the product id it is
+/// bought as on the bazaar, or the item key it is sold under on the AH when that's the only place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    pub product_id: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentValue {
+    pub product_id: String,
+    pub count: u32,
+    /// `None` when neither the bazaar nor the AH had a price for it.
+    pub unit_price: Option<i64>,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Valuation {
+    pub item_key: Option<String>,
+    /// Lowest BIN of the item key, what a clean copy of the item goes for.
+    pub base_price: Option<i64>,
+    pub components: Vec<ComponentValue>,
+}
+
+impl Valuation {
+    pub fn modifiers_value(&self) -> i64 {
+        self.components.iter().map(|c| c.value).sum()
+    }
+}
+
+/// Lists every priced upgrade applied to an item.
+pub fn modifiers(attributes: &ExtraAttributes) -> Vec<Modifier> {
+    let mut modifiers = Vec::new();
+    let mut push = |product_id: String, count: u32| {
+        if count > 0 {
+            modifiers.push(Modifier { product_id, count });
+        }
+    };
+    // The first ten books are hot potato books, anything past that is fuming.
+    if let Some(books) = attributes.hot_potato_count {
+        let books = books as u32;
+        push("HOT_POTATO_BOOK".to_string(), books.min(10));
+        push("FUMING_POTATO_BOOK".to_string(), books.saturating_sub(10));
+    }
+    if attributes.rarity_upgrades == Some(true) {
+        push("RECOMBOBULATOR_3000".to_string(), 1);
+    }
+    if let Some(count) = attributes.art_of_war_count {
+        push("THE_ART_OF_WAR".to_string(), count);
+    }
+    if let Some(count) = attributes.farming_for_dummies_count {
+        push("FARMING_FOR_DUMMIES".to_string(), count);
+    }
+    // A book's enchant is the item itself, it is already priced by its item key.
+    if let Some(enchantments) = attributes
+        .enchantments
+        .as_ref()
+        .filter(|_| attributes.id != "ENCHANTED_BOOK")
+    {
+        for (name, level) in enchantments {
+            push(format!("ENCHANTMENT_{}_{}", name.to_uppercase(), level), 1);
+        }
+    }
+    if let Some(scrolls) = &attributes.ability_scroll {
+        for scroll in scrolls {
+            push(scroll.clone(), 1);
+        }
+    }
+    if let Some(gems) = &attributes.gems {
+        for (slot, gem) in gems {
+            let quality = match gem {
+                Gem::SlottedGem(quality) => quality,
+                Gem::SlottedGemStruct(gem) => &gem.quality,
+                Gem::UnlockedSlot(_) => continue,
+            };
+            if slot.ends_with("_gem") {
+                continue;
+            }
+            // Gem specific slots are named after the gem ("RUBY_0"), while universal slots
+            // ("COMBAT_0") keep the gem type in a sibling "COMBAT_0_gem" entry.
+            let gem_type = match gems.get(&format!("{}_gem", slot)) {
+                Some(Gem::SlottedGem(gem_type)) => gem_type.as_str(),
+                _ => slot.rsplit_once('_').map_or(slot.as_str(), |(t, _)| t),
+            };
+            push(format!("{}_{}_GEM", quality, gem_type), 1);
+        }
+    }
+    modifiers
+}
+
+/// Looks up a modifier on the bazaar, falling back to the lowest BIN on the AH.
+fn unit_price(book: &AuctionBook, bazaar: &Bazaar, product_id: &str) -> Option<i64> {
+    bazaar
+        .buy_price(product_id)
+        .or_else(|| book.lowest_bin(&ah_item_key(product_id), None))
+}
+
+/// The item key a modifier is listed under on the AH. Enchantments are sold as books, keyed the
+/// way [`models::item_key`] keys single enchant books.
+fn ah_item_key(product_id: &str) -> String {
+    match product_id.strip_prefix("ENCHANTMENT_") {
+        Some(enchantment) => format!("ENCHANTED_BOOK_{}", enchantment),
+        None => product_id.to_string(),
+    }
+}
+
+/// Prices every modifier applied to an item.
+pub fn price_modifiers(
+    book: &AuctionBook,
+    bazaar: &Bazaar,
+    attributes: &ExtraAttributes,
+) -> Vec<ComponentValue> {
+    modifiers(attributes)
+        .into_iter()
+        .map(|modifier| {
+            let unit_price = unit_price(book, bazaar, &modifier.product_id);
+            ComponentValue {
+                value: unit_price.unwrap_or(0) * modifier.count as i64,
+                product_id: modifier.product_id,
+                count: modifier.count,
+                unit_price,
+            }
+        })
+        .collect()
+}
+
+/// Estimates what an auction's item is worth as its base price plus everything applied to it.
+pub fn appraise(book: &AuctionBook, bazaar: &Bazaar, auction: &Auction) -> Valuation {
+    let Some(item) = &auction.item_data else {
+        return Valuation::default();
+    };
+    let item_key = models::item_key_for(auction);
+    let base_price = item_key
+        .as_deref()
+        .and_then(|key| book.lowest_bin(key, Some(&auction.uuid)));
+    Valuation {
+        item_key,
+        base_price,
+        components: price_modifiers(book, bazaar, &item.tag.extra_attributes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_auction;
+
+    #[test]
+    fn enchantments_fall_back_to_books_on_the_ah() {
+        let book_key = models::item_key(
+            "ENCHANTED_BOOK",
+            None,
+            "LEGENDARY",
+            Some(&["ultimate_wise 5".to_string()]),
+        );
+        assert_eq!(ah_item_key("ENCHANTMENT_ULTIMATE_WISE_5"), book_key);
+        let mut book = AuctionBook::default();
+        book.insert(test_auction("book", &book_key, 3_000_000, true));
+        let price = unit_price(&book, &Bazaar::default(), "ENCHANTMENT_ULTIMATE_WISE_5");
+        assert_eq!(price, Some(3_000_000));
+        assert_eq!(
+            unit_price(&book, &Bazaar::default(), "RECOMBOBULATOR_3000"),
+            None
+        );
+    }
+}
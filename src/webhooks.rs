@@ -0,0 +1,83 @@
+use crate::shutdown::Shutdown;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+/// Posts JSON payloads to webhook urls in the background, retrying failed deliveries.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    shutdown: Shutdown,
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        WebhookDispatcher::new(5, Duration::from_secs(1))
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> Self {
+        WebhookDispatcher {
+            client: reqwest::Client::new(),
+            max_attempts: max_attempts.max(1),
+            initial_backoff,
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    /// Runs deliveries as tasks `shutdown` waits on, and stops retrying them once it's requested.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Delivers `payload` to `url`, doubling the wait between each failed attempt. Gives up early
+    /// if shutdown is requested while waiting.
+    pub async fn send<T: Serialize>(&self, url: &str, payload: &T) -> Result<(), reqwest::Error> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .client
+                .post(url)
+                .json(payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt >= self.max_attempts => return Err(e),
+                Err(e) => {
+                    println!(
+                        "Webhook delivery to {} failed (attempt {}/{}): {}",
+                        url, attempt, self.max_attempts, e
+                    );
+                    if !self.shutdown.sleep(backoff).await {
+                        return Err(e);
+                    }
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Like [`WebhookDispatcher::send`] but without waiting for the delivery to finish, running
+    /// `gave_up` once every attempt failed or shutdown cut the retries short.
+    pub fn dispatch<T, F>(&self, url: String, payload: T, gave_up: F)
+    where
+        T: Serialize + Send + Sync + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let dispatcher = self.clone();
+        self.shutdown.spawn(async move {
+            if let Err(e) = dispatcher.send(&url, &payload).await {
+                println!("Giving up on webhook delivery to {}: {}", url, e);
+                gave_up.await;
+            }
+        });
+    }
+}